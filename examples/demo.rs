@@ -19,6 +19,8 @@ enum ShadowTestRole {
     Candidate,
     #[strum(ascii_case_insensitive)]
     Upstream,
+    #[strum(ascii_case_insensitive, serialize = "secondary-reference")]
+    SecondaryReference,
 }
 static SHADOW_TEST_HEADER: HeaderName = HeaderName::from_static("x-shadow-test-role");
 
@@ -0,0 +1,114 @@
+use crate::settings::{Config, HeaderComparison, LatencyBudget, Route};
+use crate::util::json_path::JsonPath;
+use http::HeaderName;
+use std::time::Duration;
+
+/// rules how to compare the responses of reference and candidate for a specific route
+#[derive(Debug, Default)]
+pub struct Comparison {
+    /// paths to remove from JSON bodies before comparing
+    pub ignore: Vec<JsonPath>,
+
+    /// which headers to compare
+    pub headers: HeaderComparison,
+
+    /// how much slower than the reference the candidate may be
+    pub latency_budget: LatencyBudget,
+}
+
+impl Comparison {
+    /// build the rules for a route, i.e. merge route-specific rules with the global ones
+    pub fn new(config: &Config, route: &Route) -> Self {
+        let ignore = config
+            .ignore
+            .iter()
+            .chain(route.ignore.iter())
+            .cloned()
+            .collect();
+
+        let headers = route.headers.as_ref().unwrap_or(&config.headers).clone();
+
+        Self {
+            ignore,
+            headers,
+            latency_budget: route.latency_budget.unwrap_or(config.latency_budget),
+        }
+    }
+
+    /// check if the given header is relevant for comparison
+    pub fn compares_header(&self, name: &HeaderName) -> bool {
+        let listed = |names: &[String]| names.iter().any(|n| n.eq_ignore_ascii_case(name.as_str()));
+
+        (self.headers.compare.iter().any(|n| n == "*") || listed(&self.headers.compare))
+            && !listed(&self.headers.ignore)
+    }
+
+    /// check if the candidate is slower than the reference by more than the budget allows
+    pub fn exceeds_latency_budget(&self, reference: Duration, candidate: Duration) -> bool {
+        let LatencyBudget { absolute, relative } = self.latency_budget;
+        let slower = candidate.saturating_sub(reference);
+
+        absolute.is_some_and(|absolute| slower > absolute)
+            || relative
+                .is_some_and(|relative| slower.as_secs_f64() > reference.as_secs_f64() * relative)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Comparison;
+    use crate::settings::{HeaderComparison, LatencyBudget};
+    use http::header::{CONTENT_TYPE, DATE, LOCATION};
+    use std::time::Duration;
+
+    #[test]
+    fn test_compares_header() {
+        let comparison = Comparison {
+            headers: HeaderComparison {
+                compare: vec!["Location".to_string()],
+                ignore: vec![],
+            },
+            ..Default::default()
+        };
+
+        assert!(comparison.compares_header(&LOCATION));
+        assert!(!comparison.compares_header(&DATE));
+    }
+
+    #[test]
+    fn test_compares_all_headers_but_ignored() {
+        let comparison = Comparison {
+            headers: HeaderComparison {
+                compare: vec!["*".to_string()],
+                ignore: vec!["date".to_string()],
+            },
+            ..Default::default()
+        };
+
+        assert!(comparison.compares_header(&CONTENT_TYPE));
+        assert!(!comparison.compares_header(&DATE));
+    }
+
+    #[test]
+    fn test_latency_budget() {
+        let ms = Duration::from_millis;
+        let comparison = |absolute, relative| Comparison {
+            latency_budget: LatencyBudget { absolute, relative },
+            ..Default::default()
+        };
+
+        // no budget
+        assert!(!comparison(None, None).exceeds_latency_budget(ms(10), ms(1000)));
+
+        assert!(comparison(Some(ms(50)), None).exceeds_latency_budget(ms(100), ms(151)));
+        assert!(!comparison(Some(ms(50)), None).exceeds_latency_budget(ms(100), ms(150)));
+        assert!(comparison(None, Some(0.2)).exceeds_latency_budget(ms(100), ms(121)));
+        assert!(!comparison(None, Some(0.2)).exceeds_latency_budget(ms(100), ms(90)));
+
+        // exceeding either limit is enough
+        let both = comparison(Some(ms(50)), Some(0.2));
+        assert!(both.exceeds_latency_budget(ms(10), ms(30)));
+        assert!(both.exceeds_latency_budget(ms(1000), ms(1100)));
+        assert!(!both.exceeds_latency_budget(ms(100), ms(115)));
+    }
+}
@@ -1,50 +1,242 @@
-use crate::http::model::{RequestContext, RequestMode};
-use crate::settings::Route;
-use bytes::Bytes;
+use crate::diff::comparison::Comparison;
+use crate::diff::error::{Control, InvalidConfig};
+use crate::diff::predicate::Predicate;
+use crate::diff::redact::{BodyRedaction, HeaderCapture};
+use crate::diff::rewrite::Rewrite;
+use crate::diff::sampling::Sampling;
+use crate::http::model::{Candidate, Experiment, RequestContext, RequestMode};
+use crate::http::upgrade;
+use crate::metrics;
+use crate::settings::{self, Config, Route, SampleBy, UpstreamTimeouts};
 use http::uri::PathAndQuery;
 use matchit::Match;
-use tokio::sync::oneshot;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, oneshot};
+use tracing::debug;
 
-/// the dispatcher decides where to send the request, i.e. who is reference, who is candidate, test anything at all
+/// a configured route, along with everything that can be derived from the config upfront
+struct Entry {
+    route: Route,
+    /// base-URLs of the candidates of this route
+    candidates: Vec<Candidate>,
+    rewrite: Option<Arc<Rewrite>>,
+    comparison: Arc<Comparison>,
+    redact_body: Option<Arc<BodyRedaction>>,
+    timeouts: UpstreamTimeouts,
+    /// limits the number of in-flight mirror-tasks for this route
+    mirror_permits: Option<Arc<Semaphore>>,
+    predicate: Predicate,
+    sampling: Sampling,
+    /// paused at runtime, requests are just proxied
+    paused: AtomicBool,
+}
+
+/// current state of a route, as reported by the management-API
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RouteState {
+    pub path: String,
+    pub paused: bool,
+    pub sample_rate: f64,
+    /// names of the candidates, `null` for the unnamed candidate
+    pub candidates: Vec<Option<String>>,
+}
+
+/// the dispatcher decides where to send the request, i.e. who is reference, who is candidate, test anything at all.
+///
+/// The routes may be replaced at runtime, requests in flight keep the routes they started with
 pub struct Dispatcher {
-    default_candidate_base: String,
-    default_reference_base: String,
-    router: matchit::Router<Route>,
+    current: RwLock<Arc<Routes>>,
+    /// held while reloading, so concurrent reloads (e.g. by the file-watcher and the management-API) don't build on
+    /// the same routes
+    reloading: Mutex<()>,
+    /// all experiments are paused (kill switch), requests are just proxied
+    paused: AtomicBool,
 }
 
 impl Dispatcher {
-    pub fn new(
-        default_reference_base: String,
-        default_candidate_base: String,
-        routes: &[Route],
-    ) -> Self {
+    pub fn new(config: &Config) -> Result<Self, InvalidConfig> {
+        Ok(Self {
+            current: RwLock::new(Arc::new(Routes::new(config, None)?)),
+            reloading: Mutex::new(()),
+            paused: AtomicBool::new(false),
+        })
+    }
+
+    /// replace routes, upstreams and comparison-rules atomically. Keeps the current ones if the config is invalid.
+    ///
+    /// Routes paused at runtime stay paused, sample-rates changed at runtime are kept. Limits of mirror-tasks that didn't
+    /// change keep counting the tasks in flight
+    pub fn reload(&self, config: &Config) -> Result<(), InvalidConfig> {
+        let _reloading = self.reloading.lock().expect("reload-lock poisoned");
+        let routes = Routes::new(config, Some(&self.current()))?;
+        let mut current = self.current.write().expect("routes-lock poisoned");
+        for entry in &routes.entries {
+            let Ok(old) = current.entry(&entry.route.path) else {
+                continue;
+            };
+            if old.paused.load(Ordering::Relaxed) {
+                entry.paused.store(true, Ordering::Relaxed);
+            }
+            if let Some(rate) = old.sampling.overridden_rate() {
+                entry.sampling.set_rate(rate)?;
+            }
+        }
+        *current = Arc::new(routes);
+        Ok(())
+    }
+
+    /// pause or resume all experiments
+    pub fn pause(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// pause or resume the experiment of a single route
+    pub fn pause_route(&self, path: &str, paused: bool) -> Result<(), Control> {
+        // hold the lock while storing, so a concurrent reload carries the change over to the new routes
+        let current = self.current.read().expect("routes-lock poisoned");
+        current.entry(path)?.paused.store(paused, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// change the sample-rate of a route, replacing the configured one until restarted
+    pub fn set_sample_rate(&self, path: &str, rate: f64) -> Result<(), Control> {
+        // hold the lock while storing, so a concurrent reload carries the change over to the new routes
+        let current = self.current.read().expect("routes-lock poisoned");
+        Ok(current.entry(path)?.sampling.set_rate(rate)?)
+    }
+
+    /// current state of all routes, in the order configured
+    pub fn routes(&self) -> Vec<RouteState> {
+        self.current()
+            .entries
+            .iter()
+            .map(|e| RouteState {
+                path: e.route.path.clone(),
+                paused: e.paused.load(Ordering::Relaxed),
+                sample_rate: e.sampling.rate(),
+                candidates: e.candidates.iter().map(|c| c.name.clone()).collect(),
+            })
+            .collect()
+    }
+
+    fn current(&self) -> Arc<Routes> {
+        self.current.read().expect("routes-lock poisoned").clone()
+    }
+
+    /// init a request-context. Decide if this is a request under test, or a normal request,
+    /// and initialize all the required data
+    pub fn init_context<B>(&self, req: &http::Request<B>) -> RequestContext {
+        self.current().init_context(req, self.is_paused())
+    }
+}
+
+/// everything derived from the config to dispatch requests
+struct Routes {
+    default_reference_base: String,
+    default_secondary_reference_base: Option<String>,
+    default_timeouts: UpstreamTimeouts,
+    /// limits the number of in-flight mirror-tasks for all routes
+    mirror_permits: Option<Arc<Semaphore>>,
+    max_mirror_tasks: Option<usize>,
+    max_body_size: usize,
+    /// which request-headers to include in samples
+    headers: Arc<HeaderCapture>,
+    router: matchit::Router<Arc<Entry>>,
+    /// all entries, in the order configured
+    entries: Vec<Arc<Entry>>,
+}
+
+impl Routes {
+    /// `current`: the routes replaced on reload, to carry over what must survive it
+    fn new(config: &Config, current: Option<&Routes>) -> Result<Self, InvalidConfig> {
         let mut router = matchit::Router::new();
+        let mut entries = vec![];
+
+        for r in &config.routes {
+            // candidates of the route replace the global ones
+            let candidates = if r.candidate.is_some() || !r.candidates.is_empty() {
+                candidates(r, r.candidate.as_ref(), &r.candidates)?
+            } else {
+                candidates(r, config.candidate.as_ref(), &config.candidates)?
+            };
+            if candidates.is_empty() {
+                return Err(InvalidConfig::NoCandidate(r.path.clone()));
+            }
+
+            let sample_by = r.sample_by.clone().or_else(|| config.sample_by.clone());
+            // sampling by a parameter the route doesn't have would silently sample randomly
+            if let Some(SampleBy::Param(name)) = &sample_by {
+                if !r.path.contains(&format!("{{{name}}}"))
+                    && !r.path.contains(&format!("{{*{name}}}"))
+                {
+                    return Err(InvalidConfig::SampleByParameter(
+                        r.path.clone(),
+                        name.clone(),
+                    ));
+                }
+            }
 
-        for r in routes {
+            let entry = Arc::new(Entry {
+                route: r.clone(),
+                candidates,
+                rewrite: Rewrite::new(r)?.map(Arc::new),
+                comparison: Arc::new(Comparison::new(config, r)),
+                redact_body: BodyRedaction::new(config, r)?.map(Arc::new),
+                timeouts: UpstreamTimeouts {
+                    reference: r.timeouts.reference.or(config.timeouts.reference),
+                    candidate: r.timeouts.candidate.or(config.timeouts.candidate),
+                },
+                mirror_permits: mirror_permits(
+                    r.max_mirror_tasks,
+                    current
+                        .and_then(|c| c.entry(&r.path).ok())
+                        .map(|e| (e.route.max_mirror_tasks, &e.mirror_permits)),
+                ),
+                predicate: Predicate::new(r)?,
+                sampling: Sampling::new(r.sample_rate.unwrap_or(config.sample_rate), sample_by)?,
+                paused: AtomicBool::new(false),
+            });
             router
-                .insert(&r.path, r.clone())
-                .expect("invalid path provided");
+                .insert(&r.path, entry.clone())
+                .map_err(|e| InvalidConfig::Path(r.path.clone(), e))?;
+            entries.push(entry);
         }
 
-        Self {
-            default_candidate_base,
-            default_reference_base,
+        Ok(Self {
+            default_reference_base: config.reference.clone(),
+            default_secondary_reference_base: config.secondary_reference.clone(),
+            default_timeouts: config.timeouts,
+            mirror_permits: mirror_permits(
+                config.max_mirror_tasks,
+                current.map(|c| (c.max_mirror_tasks, &c.mirror_permits)),
+            ),
+            max_mirror_tasks: config.max_mirror_tasks,
+            max_body_size: config.max_body_size,
+            headers: Arc::new(HeaderCapture::new(&config.request_headers)?),
             router,
-        }
+            entries,
+        })
     }
 
     /// build the request-context with all data required to mirror traffic (and publish
     fn init_context_for_experiment(
         &self,
-        request: &http::Request<Bytes>,
         path_query: &str,
-        matched_route: &Match<&Route>,
+        matched_route: &Match<&Arc<Entry>>,
+        permits: Vec<OwnedSemaphorePermit>,
     ) -> RequestContext {
         // remember: this runs on the main "thread", so do as little work as possible!
         let (tx, rx) = oneshot::channel();
 
-        let route_value = matched_route.value;
-        let params = matched_route
+        let route_value = &matched_route.value.route;
+        let params: Vec<_> = matched_route
             .params
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_string()))
@@ -54,31 +246,53 @@ impl Dispatcher {
             .reference
             .as_ref()
             .unwrap_or(&self.default_reference_base);
-        let candidate_base = route_value
-            .candidate
+        let secondary_reference_base = route_value
+            .secondary_reference
             .as_ref()
-            .unwrap_or(&self.default_candidate_base);
+            .or(self.default_secondary_reference_base.as_ref());
 
         let reference_uri = format!("{reference_base}{path_query}");
-        let candidate_uri = format!("{candidate_base}{path_query}");
+        let rewrite = matched_route.value.rewrite.clone();
+        let candidate_path_query = match &rewrite {
+            Some(rewrite) => Cow::Owned(rewrite.path_query(path_query, &params)),
+            None => Cow::Borrowed(path_query),
+        };
+        let candidates = matched_route
+            .value
+            .candidates
+            .iter()
+            .map(|c| Candidate {
+                name: c.name.clone(),
+                uri: format!("{}{candidate_path_query}", c.uri),
+            })
+            .collect();
+        let secondary_reference_uri =
+            secondary_reference_base.map(|base| format!("{base}{path_query}"));
 
         RequestContext {
             reference_uri,
+            reference_timeouts: matched_route.value.timeouts.reference,
             tx: Some(tx),
-            mode: RequestMode::Experiment {
+            mode: RequestMode::Experiment(Box::new(Experiment {
                 key: route_value.key.clone(),
                 route: route_value.path.clone(),
                 route_params: params,
-                request: request.clone(),
-                candidate_uri,
+                candidates,
+                rewrite,
+                secondary_reference_uri,
+                headers: self.headers.clone(),
+                comparison: matched_route.value.comparison.clone(),
+                redact_body: matched_route.value.redact_body.clone(),
+                timeouts: matched_route.value.timeouts,
+                max_body_size: self.max_body_size,
+                permits,
                 rx,
-            },
+            })),
         }
     }
 
-    /// init a request-context. Decide if this is a request under test, or a normal request,
-    /// and initialize all the required data
-    pub fn init_context(&self, req: &http::Request<Bytes>) -> RequestContext {
+    /// `paused`: all experiments are paused, requests are just proxied
+    fn init_context<B>(&self, req: &http::Request<B>, paused: bool) -> RequestContext {
         let uri = req.uri();
 
         let parameters = self.router.at(uri.path()).ok();
@@ -87,15 +301,470 @@ impl Dispatcher {
             .path_and_query()
             .map_or(uri.path(), PathAndQuery::as_str);
 
-        if let Some(m) = parameters {
-            self.init_context_for_experiment(req, path_query, &m)
-        } else {
-            let reference_uri = format!("{}{}", self.default_reference_base, path_query);
-            RequestContext {
-                reference_uri,
-                tx: None,
-                mode: RequestMode::Proxy,
+        // upgraded connections (e.g. WebSockets) can't be mirrored
+        if upgrade::is_requested(req) {
+            return self.init_context_for_proxy(path_query, parameters.map(|m| m.value));
+        }
+
+        match parameters {
+            Some(m)
+                if paused
+                    || m.value.paused.load(Ordering::Relaxed)
+                    || !m.value.predicate.matches(req)
+                    || !m.value.sampling.is_sampled(&m.params, req.headers()) =>
+            {
+                self.init_context_for_proxy(path_query, Some(m.value))
             }
+            Some(m) => match self.acquire_mirror_permits(m.value) {
+                Some(permits) => self.init_context_for_experiment(path_query, &m, permits),
+                None => {
+                    metrics::DROPPED_EXPERIMENTS
+                        .with_label_values(&[&m.value.route.path, "mirror_tasks"])
+                        .inc();
+                    debug!(
+                        "too many mirror-tasks in flight, not mirroring request to {}",
+                        m.value.route.path
+                    );
+                    self.init_context_for_proxy(path_query, Some(m.value))
+                }
+            },
+            None => self.init_context_for_proxy(path_query, None),
+        }
+    }
+
+    /// build the request-context to simply proxy the request to the reference
+    fn init_context_for_proxy(
+        &self,
+        path_query: &str,
+        entry: Option<&Arc<Entry>>,
+    ) -> RequestContext {
+        let reference_base = entry
+            .and_then(|e| e.route.reference.as_ref())
+            .unwrap_or(&self.default_reference_base);
+
+        RequestContext {
+            reference_uri: format!("{reference_base}{path_query}"),
+            reference_timeouts: entry
+                .map_or(self.default_timeouts.reference, |e| e.timeouts.reference),
+            tx: None,
+            mode: RequestMode::Proxy,
+        }
+    }
+
+    /// the entry configured for exactly this path (in matchit-syntax)
+    fn entry(&self, path: &str) -> Result<&Entry, Control> {
+        self.entries
+            .iter()
+            .find(|e| e.route.path == path)
+            .map(AsRef::as_ref)
+            .ok_or_else(|| Control::UnknownRoute(path.to_string()))
+    }
+
+    /// try to acquire permits (globally and for the route) to spawn a mirror-task.
+    ///
+    /// Returns None if any limit is exceeded.
+    fn acquire_mirror_permits(&self, entry: &Entry) -> Option<Vec<OwnedSemaphorePermit>> {
+        [&self.mirror_permits, &entry.mirror_permits]
+            .into_iter()
+            .flatten()
+            .map(|semaphore| semaphore.clone().try_acquire_owned().ok())
+            .collect()
+    }
+}
+
+/// a semaphore limiting mirror-tasks to `limit`, if any. Reuses the `current` one (along with its limit) if the limit is
+/// unchanged, so mirror-tasks in flight still count
+fn mirror_permits(
+    limit: Option<usize>,
+    current: Option<(Option<usize>, &Option<Arc<Semaphore>>)>,
+) -> Option<Arc<Semaphore>> {
+    match current {
+        Some((current_limit, semaphore)) if current_limit == limit => semaphore.clone(),
+        _ => limit.map(|n| Arc::new(Semaphore::new(n))),
+    }
+}
+
+/// the (unnamed) candidate and the named candidates, names must be unique
+fn candidates(
+    route: &Route,
+    candidate: Option<&String>,
+    named: &[settings::Candidate],
+) -> Result<Vec<Candidate>, InvalidConfig> {
+    let mut names = std::collections::HashSet::new();
+    let named = named.iter().map(|c| {
+        if !names.insert(&c.name) {
+            return Err(InvalidConfig::DuplicateCandidate(
+                route.path.clone(),
+                c.name.clone(),
+            ));
         }
+        Ok(Candidate {
+            name: Some(c.name.clone()),
+            uri: c.url.clone(),
+        })
+    });
+
+    candidate
+        .map(|uri| {
+            Ok(Candidate {
+                name: None,
+                uri: uri.clone(),
+            })
+        })
+        .into_iter()
+        .chain(named)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dispatcher;
+    use crate::diff::error::InvalidConfig;
+    use crate::http::model::{Candidate, RequestMode};
+    use crate::settings;
+    use bytes::Bytes;
+    use http::HeaderValue;
+
+    fn dispatcher(toml: &str) -> Dispatcher {
+        Dispatcher::new(&settings::from_toml(toml)).expect("config must be valid")
+    }
+
+    fn request(uri: &str) -> http::Request<Bytes> {
+        http::Request::get(uri)
+            .body(Bytes::new())
+            .expect("request must be valid")
+    }
+
+    #[test]
+    fn test_limit_mirror_tasks() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/api/{value}", max_mirror_tasks = 1 }]
+            "#,
+        );
+
+        let first = dispatcher.init_context(&request("/api/1"));
+        assert!(matches!(first.mode, RequestMode::Experiment(_)));
+
+        let second = dispatcher.init_context(&request("/api/2"));
+        assert!(matches!(second.mode, RequestMode::Proxy));
+        assert_eq!(second.reference_uri, "http://reference/api/2");
+
+        // finishing the first experiment releases its permit
+        drop(first);
+        let third = dispatcher.init_context(&request("/api/3"));
+        assert!(matches!(third.mode, RequestMode::Experiment(_)));
+    }
+
+    #[test]
+    fn test_limit_mirror_tasks_on_reload() {
+        let config = |limit| {
+            settings::from_toml(&format!(
+                r#"
+                reference = "http://reference"
+                candidate = "http://candidate"
+                max_mirror_tasks = {limit}
+                routes = [
+                    {{ path = "/route/{{value}}", max_mirror_tasks = 1 }},
+                    {{ path = "/global/{{value}}" }},
+                ]
+                "#
+            ))
+        };
+        let dispatcher = Dispatcher::new(&config(2)).expect("config must be valid");
+        let is_experiment = |path| {
+            matches!(
+                dispatcher.init_context(&request(path)).mode,
+                RequestMode::Experiment(_)
+            )
+        };
+
+        let route = dispatcher.init_context(&request("/route/1"));
+        let global = dispatcher.init_context(&request("/global/1"));
+        assert!(matches!(route.mode, RequestMode::Experiment(_)));
+        assert!(matches!(global.mode, RequestMode::Experiment(_)));
+
+        // unchanged limits keep counting the tasks in flight
+        dispatcher.reload(&config(2)).expect("config must be valid");
+        assert!(!is_experiment("/route/2"));
+        assert!(!is_experiment("/global/2"));
+
+        // changed limits start over
+        dispatcher.reload(&config(3)).expect("config must be valid");
+        assert!(is_experiment("/global/2"));
+        assert!(!is_experiment("/route/2"));
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            sample_rate = 0.0
+            routes = [
+                { path = "/never/{value}" },
+                { path = "/always/{value}", sample_rate = 1.0 },
+            ]
+            "#,
+        );
+
+        let never = dispatcher.init_context(&request("/never/1"));
+        assert!(matches!(never.mode, RequestMode::Proxy));
+
+        let always = dispatcher.init_context(&request("/always/1"));
+        assert!(matches!(always.mode, RequestMode::Experiment(_)));
+    }
+
+    #[test]
+    fn test_sample_by_unknown_parameter() {
+        let config = |routes| {
+            settings::from_toml(&format!(
+                r#"
+                reference = "http://reference"
+                candidate = "http://candidate"
+                sample_by = {{ param = "id" }}
+                routes = {routes}
+                "#
+            ))
+        };
+
+        assert!(
+            Dispatcher::new(&config(
+                r#"[{ path = "/user/{id}" }, { path = "/file/{*id}" }]"#
+            ))
+            .is_ok()
+        );
+        assert!(matches!(
+            Dispatcher::new(&config(r#"[{ path = "/user/{user}" }]"#)),
+            Err(InvalidConfig::SampleByParameter(path, param)) if path == "/user/{user}" && param == "id"
+        ));
+        // the route's sample_by replaces the global one
+        assert!(
+            Dispatcher::new(&config(
+                r#"[{ path = "/user/{user}", sample_by = { param = "user" } }]"#
+            ))
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_never_mirror_upgrades() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/ws", reference = "http://ws-reference" }]
+            "#,
+        );
+
+        let mut req = request("/ws");
+        req.headers_mut().insert(
+            http::header::CONNECTION,
+            HeaderValue::from_static("upgrade"),
+        );
+        req.headers_mut()
+            .insert(http::header::UPGRADE, HeaderValue::from_static("websocket"));
+
+        let actual = dispatcher.init_context(&req);
+        assert!(matches!(actual.mode, RequestMode::Proxy));
+        assert_eq!(actual.reference_uri, "http://ws-reference/ws");
+    }
+
+    #[test]
+    fn test_predicate() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/user/{id}", methods = ["GET"] }]
+            "#,
+        );
+
+        let get = dispatcher.init_context(&request("/user/1"));
+        assert!(matches!(get.mode, RequestMode::Experiment(_)));
+
+        let mut delete = request("/user/1");
+        *delete.method_mut() = http::Method::DELETE;
+        let delete = dispatcher.init_context(&delete);
+        assert!(matches!(delete.mode, RequestMode::Proxy));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            candidates = [{ name = "hotfix", url = "http://hotfix" }]
+            routes = [
+                { path = "/default" },
+                { path = "/rewrite", candidates = [{ name = "rewrite", url = "http://rewrite" }] },
+            ]
+            "#,
+        );
+
+        let candidates = |path| match dispatcher.init_context(&request(path)).mode {
+            RequestMode::Experiment(experiment) => experiment.candidates,
+            RequestMode::Proxy => panic!("expected an experiment for {path}"),
+        };
+
+        assert_eq!(
+            candidates("/default?a=b"),
+            vec![
+                Candidate {
+                    name: None,
+                    uri: "http://candidate/default?a=b".to_string()
+                },
+                Candidate {
+                    name: Some("hotfix".to_string()),
+                    uri: "http://hotfix/default?a=b".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            candidates("/rewrite"),
+            vec![Candidate {
+                name: Some("rewrite".to_string()),
+                uri: "http://rewrite/rewrite".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/user/{id}", rewrite = { path = "/v2/users/{id}" } }]
+            "#,
+        );
+
+        let actual = dispatcher.init_context(&request("/user/1?a=b"));
+        assert_eq!(actual.reference_uri, "http://reference/user/1?a=b");
+        let RequestMode::Experiment(experiment) = actual.mode else {
+            panic!("expected an experiment");
+        };
+        assert_eq!(
+            experiment.candidates[0].uri,
+            "http://candidate/v2/users/1?a=b"
+        );
+    }
+
+    #[test]
+    fn test_reload() {
+        let dispatcher = dispatcher(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/a" }]
+            "#,
+        );
+        let in_flight = dispatcher.init_context(&request("/a"));
+
+        dispatcher
+            .reload(&settings::from_toml(
+                r#"
+                reference = "http://new-reference"
+                candidate = "http://candidate"
+                routes = [{ path = "/b" }]
+                "#,
+            ))
+            .expect("config must be valid");
+        assert!(matches!(in_flight.mode, RequestMode::Experiment(_)));
+        assert!(matches!(
+            dispatcher.init_context(&request("/a")).mode,
+            RequestMode::Proxy
+        ));
+        let b = dispatcher.init_context(&request("/b"));
+        assert!(matches!(b.mode, RequestMode::Experiment(_)));
+        assert_eq!(b.reference_uri, "http://new-reference/b");
+
+        // invalid configs are rejected, the current routes are kept
+        let invalid = dispatcher.reload(&settings::from_toml(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/c", sample_rate = 2.0 }]
+            "#,
+        ));
+        assert_eq!(
+            invalid.map_err(|e| e.to_string()),
+            Err("sample_rate must be between 0.0 and 1.0, got 2".to_string())
+        );
+        assert!(matches!(
+            dispatcher.init_context(&request("/b")).mode,
+            RequestMode::Experiment(_)
+        ));
+    }
+
+    #[test]
+    fn test_pause() {
+        let toml = r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/a" }, { path = "/b" }]
+            "#;
+        let dispatcher = dispatcher(toml);
+        let is_experiment = |path| {
+            matches!(
+                dispatcher.init_context(&request(path)).mode,
+                RequestMode::Experiment(_)
+            )
+        };
+
+        dispatcher
+            .pause_route("/a", true)
+            .expect("route must exist");
+        assert!(!is_experiment("/a"));
+        assert!(is_experiment("/b"));
+        assert!(dispatcher.pause_route("/c", true).is_err());
+
+        // the route stays paused after reloading
+        dispatcher
+            .reload(&settings::from_toml(toml))
+            .expect("config must be valid");
+        assert!(!is_experiment("/a"));
+
+        dispatcher.pause(true);
+        assert!(!is_experiment("/b"));
+        dispatcher.pause(false);
+        dispatcher
+            .pause_route("/a", false)
+            .expect("route must exist");
+        assert!(is_experiment("/a"));
+        assert!(is_experiment("/b"));
+    }
+
+    #[test]
+    fn test_sample_rate_on_reload() {
+        let toml = r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/a", sample_rate = 0.5 }, { path = "/b", sample_rate = 0.5 }]
+            "#;
+        let dispatcher = dispatcher(toml);
+        let sample_rates = || {
+            dispatcher
+                .routes()
+                .iter()
+                .map(|r| r.sample_rate)
+                .collect::<Vec<_>>()
+        };
+
+        dispatcher
+            .set_sample_rate("/a", 0.1)
+            .expect("route must exist");
+        assert!(dispatcher.set_sample_rate("/a", 2.0).is_err());
+        assert_eq!(sample_rates(), vec![0.1, 0.5]);
+
+        // the changed sample-rate is kept after reloading, unchanged ones follow the config
+        dispatcher
+            .reload(&settings::from_toml(&toml.replace("0.5", "0.2")))
+            .expect("config must be valid");
+        assert_eq!(sample_rates(), vec![0.1, 0.2]);
     }
 }
@@ -0,0 +1,65 @@
+use crate::domain::{SCHEMA_VERSION, Sample};
+use crate::settings;
+use crate::util::serialization;
+use serde::Serialize;
+use std::time::SystemTime;
+
+/// type of events containing whole samples
+pub const SAMPLE: &str = "miffy.sample";
+/// type of events containing compact match-records
+pub const MATCH: &str = "miffy.match";
+
+/// a CloudEvent (v1.0) in structured mode, see <https://github.com/cloudevents/spec/blob/v1.0.2/cloudevents/spec.md>
+#[derive(Serialize)]
+struct Event<'a, T> {
+    specversion: &'static str,
+    id: String,
+    source: &'a str,
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    /// the route
+    subject: &'a str,
+    #[serde(serialize_with = "serialization::rfc3339")]
+    time: SystemTime,
+    datacontenttype: &'static str,
+    /// extension-attribute: version of miffy
+    miffyversion: &'static str,
+    /// extension-attribute: version of the format of samples
+    schemaversion: &'static str,
+    data: &'a T,
+}
+
+/// wraps published samples in a CloudEvents-envelope
+#[derive(Debug, Clone)]
+pub struct CloudEvents {
+    source: String,
+}
+
+impl CloudEvents {
+    pub fn new(settings: &settings::CloudEvents) -> Self {
+        Self {
+            source: settings.source.clone(),
+        }
+    }
+
+    /// serialize the data (the sample itself, or derived from it) as event of the given type
+    pub fn wrap<T: Serialize>(
+        &self,
+        sample: &Sample,
+        event_type: &'static str,
+        data: &T,
+    ) -> serde_json::Result<String> {
+        serde_json::to_string(&Event {
+            specversion: "1.0",
+            id: format!("{:032x}", rand::random::<u128>()),
+            source: &self.source,
+            event_type,
+            subject: &sample.request.route,
+            time: sample.timestamp,
+            datacontenttype: "application/json",
+            miffyversion: env!("CARGO_PKG_VERSION"),
+            schemaversion: SCHEMA_VERSION,
+            data,
+        })
+    }
+}
@@ -1,12 +1,57 @@
-use crate::http::error::Upstream;
 use thiserror::Error;
-use tokio::sync::oneshot::error::RecvError;
 
+/// an invalid configuration, rejected at startup or on reload
 #[derive(Debug, Error)]
-pub enum Internal {
-    #[error(transparent)]
-    RecvError(#[from] RecvError),
+pub enum InvalidConfig {
+    #[error("invalid path {0}: {1}")]
+    Path(String, matchit::InsertError),
+
+    #[error("invalid method for route {0}: {1}")]
+    Method(String, String),
+
+    #[error("invalid header-name for route {0}: {1}")]
+    HeaderName(String, String),
+
+    #[error("invalid header-value for route {0}: {1}")]
+    HeaderValue(String, String),
+
+    #[error("sample_rate must be between 0.0 and 1.0, got {0}")]
+    SampleRate(f64),
+
+    #[error("equal_samples.rate must be between 0.0 and 1.0, got {0}")]
+    EqualSampleRate(f64),
+
+    #[error("no candidate for route: {0}")]
+    NoCandidate(String),
+
+    #[error("duplicate candidate for route {0}: {1}")]
+    DuplicateCandidate(String, String),
+
+    #[error("invalid rewrite-path for route {0}: {1}")]
+    RewritePath(String, String),
+
+    #[error("unknown parameter in rewrite-path for route {0}: {1}")]
+    UnknownParameter(String, String),
+
+    #[error("unknown parameter to sample by for route {0}: {1}")]
+    SampleByParameter(String, String),
+
+    #[error("{0}-sink may only be configured once")]
+    DuplicateSink(&'static str),
+
+    #[error("invalid header-name to redact: {0}")]
+    RedactHeader(String),
+
+    #[error("invalid pattern to redact for route {0}: {1}")]
+    RedactPattern(String, regex::Error),
+}
+
+/// a runtime-change of experiments, e.g. via the management-API, that can't be applied
+#[derive(Debug, Error)]
+pub enum Control {
+    #[error("unknown route: {0}")]
+    UnknownRoute(String),
 
     #[error(transparent)]
-    Upstream(#[from] Upstream),
+    Invalid(#[from] InvalidConfig),
 }
@@ -1,16 +1,20 @@
-use crate::diff::error::Internal;
 use crate::diff::publisher::Publisher;
 use crate::domain;
 use crate::domain::Sample;
-use crate::http::SHADOW_TEST_HEADER;
-use crate::http::client::{Client, UpstreamExt};
-use crate::http::model::{ChannelValue, RequestMode};
+use crate::http::client::{self, Client, UpstreamExt};
+use crate::http::model::Experiment;
+use crate::http::{SHADOW_TEST_HEADER, full};
+use crate::metrics;
+use crate::settings::Timeouts;
 use bytes::Bytes;
-use http::{HeaderValue, Request};
-use tokio::sync::oneshot::Receiver;
-use tracing::error;
+use http::{HeaderMap, HeaderValue, Request};
+use std::time::Instant;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, warn};
 
 const SHADOW_TEST_ROLE: HeaderValue = HeaderValue::from_static("candidate");
+const SHADOW_TEST_ROLE_SECONDARY_REFERENCE: HeaderValue =
+    HeaderValue::from_static("secondary-reference");
 
 pub fn build_key(key: String, params: &[(String, String)]) -> String {
     params
@@ -20,87 +24,326 @@ pub fn build_key(key: String, params: &[(String, String)]) -> String {
         .unwrap_or(key)
 }
 
+/// key of the sample for the given candidate: named candidates are told apart by the suffix `@<name>`
+fn candidate_key(key: &str, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{key}@{name}"),
+        None => key.to_string(),
+    }
+}
+
 /// a mirror will be initialized once per request
 #[derive(Clone)]
 pub struct Mirror {
     client: Client,
+    /// client for the secondary reference, i.e. configured like the client for the reference
+    reference_client: Client,
     publisher: Publisher,
+    /// keeps track of spawned mirror-tasks, to wait for them on shutdown
+    tasks: TaskTracker,
 }
 
 impl Mirror {
-    pub fn new(publisher: Publisher) -> Self {
+    pub fn new(publisher: Publisher, client: Client, reference_client: Client) -> Self {
         Self {
-            client: hyper_util::client::legacy::Client::builder(
-                hyper_util::rt::TokioExecutor::new(),
-            )
-            .build_http(),
+            client,
+            reference_client,
             publisher,
+            tasks: TaskTracker::new(),
         }
     }
 
-    /// mirror the original request to the candidate and wait for the reference
-    pub async fn mirror(
-        &self,
-        key: Option<String>,
-        route: String,
-        route_params: Vec<(String, String)>,
-        original_request: Request<Bytes>,
-        candidate_uri: String,
-        reference_rx: Receiver<ChannelValue>,
-    ) -> Result<(), Internal> {
+    /// send a copy of the original request to the given upstream, marked with the given role.
+    ///
+    /// Returns the headers as sent too, if `capture_sent`, and the latency
+    async fn send(
+        client: &Client,
+        original_request: &Request<Bytes>,
+        uri: &str,
+        role: HeaderValue,
+        timeouts: &Timeouts,
+        max_body_size: usize,
+        capture_sent: bool,
+    ) -> (
+        Option<HeaderMap>,
+        domain::Latency,
+        Result<domain::Response, domain::Error>,
+    ) {
         let mut request = original_request.clone();
-        request
-            .headers_mut()
-            .insert(SHADOW_TEST_HEADER, SHADOW_TEST_ROLE);
+        let role_label = role.to_str().unwrap_or_default().to_string();
+        request.headers_mut().insert(SHADOW_TEST_HEADER, role);
+        let sent_headers = capture_sent.then(|| client.sent_headers(request.headers(), uri));
 
-        let response = self.client.upstream(request, &candidate_uri).await;
+        let start = Instant::now();
+        let mut ttfb = None;
+        let response = async {
+            let response = client.send(request.map(full), uri, timeouts).await?;
+            ttfb = Some(start.elapsed());
+            client::read_complete_body(response, timeouts, Some(max_body_size)).await
+        }
+        .await
+        .map(Into::into)
+        .map_err(|e| (&e).into());
+        let latency = domain::Latency {
+            ttfb,
+            total: start.elapsed(),
+        };
+        metrics::observe_upstream(&role_label, start, response.as_ref().err());
 
-        // if the sender is dropped, this will receive a RecvError, we're just logging an error then
-        let (reference_uri, reference_res) = reference_rx.await?;
-        let reference = domain::RequestResult::new(reference_uri, reference_res.map(Into::into));
+        (sent_headers, latency, response)
+    }
+
+    /// mirror the original request to the candidate (and secondary reference) and wait for the reference
+    pub async fn mirror(&self, experiment: Experiment, original_request: Request<Bytes>) {
+        let Experiment {
+            key,
+            route,
+            route_params,
+            candidates,
+            rewrite,
+            secondary_reference_uri,
+            headers,
+            comparison,
+            redact_body,
+            timeouts,
+            max_body_size,
+            // keep the permits until the sample is published
+            permits: _permits,
+            rx: reference_rx,
+        } = experiment;
 
-        let response = response.map(Into::into).map_err(|e| (&e).into());
-        let response = domain::RequestResult::new(candidate_uri, response);
+        let secondary_reference = async {
+            match &secondary_reference_uri {
+                Some(uri) => Some(
+                    Self::send(
+                        &self.reference_client,
+                        &original_request,
+                        uri,
+                        SHADOW_TEST_ROLE_SECONDARY_REFERENCE,
+                        &timeouts.reference,
+                        max_body_size,
+                        false,
+                    )
+                    .await
+                    .2,
+                ),
+                None => None,
+            }
+        };
+
+        let candidate_request = rewrite.map(|rewrite| {
+            let mut request = original_request.clone();
+            rewrite.headers(request.headers_mut());
+            request
+        });
+        let candidate_request = candidate_request.as_ref().unwrap_or(&original_request);
+
+        // fan out to all candidates concurrently
+        let candidates = futures_util::future::join_all(candidates.into_iter().map(|candidate| {
+            let (client, request, timeouts) = (&self.client, candidate_request, &timeouts);
+            let capture_sent = headers.captures_sent();
+            async move {
+                let (sent_headers, latency, response) = Self::send(
+                    client,
+                    request,
+                    &candidate.uri,
+                    SHADOW_TEST_ROLE,
+                    &timeouts.candidate,
+                    max_body_size,
+                    capture_sent,
+                )
+                .await;
+                (candidate, sent_headers, latency, response)
+            }
+        }));
+
+        let (candidates, secondary_reference) = tokio::join!(candidates, secondary_reference);
+
+        // the sender is dropped without sending if the reference's response is too large to compare (or the client went
+        // away), there's nothing to publish then
+        let Ok((reference_uri, reference_headers, reference_latency, reference_res)) =
+            reference_rx.await
+        else {
+            debug!("no response of the reference for {route}, not publishing a sample");
+            return;
+        };
+        let mut reference =
+            domain::RequestResult::new(reference_uri, reference_res.map(Into::into))
+                .with_request_headers(headers.sent(reference_headers))
+                .with_latency(reference_latency);
+
+        // values that differ between reference and secondary reference are non-deterministic, so ignore them
+        let noise = match (&reference.response, secondary_reference) {
+            (Ok(reference), Some(Ok(secondary))) => reference.noise(&secondary),
+            (_, Some(Err(e))) => {
+                warn!("secondary reference failed, not detecting noise: {e:?}");
+                vec![]
+            }
+            _ => vec![],
+        };
+
+        // redact before comparing, so differences don't reveal redacted values either
+        let redact = |body: &mut domain::Body| {
+            if let Some(redaction) = &redact_body {
+                redaction.apply(body);
+            }
+        };
+        if let Ok(response) = &mut reference.response {
+            redact(response.body_mut());
+        }
 
         let key = key.map_or_else(
             || route.clone(),
             |key| build_key(key, route_params.as_slice()),
         );
-
-        // once we have the response of the reference and the candidate, let the publisher process this sample
-        let sample = Sample::new(
-            domain::Request::new(&original_request, route, route_params),
-            reference,
-            response,
+        let mut request = domain::Request::new(
+            &original_request,
+            route,
+            route_params,
+            headers.original(original_request.headers()),
         );
-        self.publisher.publish(&key, sample).await;
+        redact(&mut request.body);
+
+        // once we have the response of the reference and the candidates, let the publisher process one sample per candidate
+        for (candidate, sent_headers, latency, mut response) in candidates {
+            if let Ok(response) = &mut response {
+                redact(response.body_mut());
+            }
+            let key = candidate_key(&key, candidate.name.as_deref());
+            let sample = Sample::new(
+                request.clone(),
+                reference.clone(),
+                domain::RequestResult::new(candidate.uri, response)
+                    .with_request_headers(headers.sent(sent_headers))
+                    .with_latency(latency),
+                candidate.name,
+                &comparison,
+                noise.clone(),
+            );
+            self.publisher.publish(&key, sample).await;
+        }
+    }
 
-        Ok(())
+    /// spawn a mirror-task for the given experiment
+    pub fn spawn(&self, experiment: Experiment, request: Request<Bytes>) {
+        let self_clone = self.clone();
+        let in_flight = InFlight::new();
+        self.tasks.spawn(async move {
+            let _in_flight = in_flight;
+            self_clone.mirror(experiment, request).await;
+        });
     }
 
-    /// spawn a mirror-task based on the given mode
-    pub fn spawn(&self, mode: RequestMode) {
-        match mode {
-            RequestMode::Proxy => {}
-            RequestMode::Experiment {
-                key,
-                route,
-                route_params,
-                request,
-                candidate_uri,
-                rx,
-            } => {
-                let self_clone = self.clone();
-                tokio::spawn(async move {
-                    // if this fails it just means the mirroring failed (for any reason). The actual request (to the reference) is not impacted
-                    if let Err(e) = self_clone
-                        .mirror(key, route, route_params, request, candidate_uri, rx)
-                        .await
-                    {
-                        error!("internal error mirroring request: {e:?}.");
-                    }
-                });
-            }
+    /// wait for pending mirror-tasks and flush the publisher, until the given deadline at most
+    pub async fn shutdown(&self, deadline: tokio::time::Instant) {
+        self.tasks.close();
+        if tokio::time::timeout_at(deadline, self.tasks.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                "{} mirror-tasks still pending, not waiting any longer",
+                self.tasks.len()
+            );
         }
+
+        self.publisher
+            .flush(deadline.saturating_duration_since(tokio::time::Instant::now()))
+            .await;
+    }
+}
+
+/// counts a mirror-task as in flight until dropped, i.e. also if the task panics or is aborted
+struct InFlight;
+
+impl InFlight {
+    fn new() -> Self {
+        metrics::MIRROR_TASKS_IN_FLIGHT.inc();
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics::MIRROR_TASKS_IN_FLIGHT.dec();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{InFlight, Mirror};
+    use crate::diff::publisher::Publisher;
+    use crate::diff::sink::{BoxFuture, Error, Sink};
+    use crate::domain::Sample;
+    use crate::http::client;
+    use crate::metrics;
+    use crate::settings::{EqualSamples, Tls};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// sink counting flushes, and remembering if the mirror-task was finished when flushed
+    #[derive(Default)]
+    struct Recorder {
+        task_finished: Arc<AtomicBool>,
+        flushes: AtomicUsize,
+        finished_before_flush: AtomicBool,
+    }
+
+    impl Sink for Recorder {
+        fn publish<'a>(
+            &'a self,
+            _key: &'a str,
+            _sample: &'a Sample,
+            _payload: &'a str,
+        ) -> BoxFuture<'a, Result<(), Error>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn flush(&self, _timeout: Duration) -> BoxFuture<'_, Result<(), Error>> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            self.finished_before_flush
+                .store(self.task_finished.load(Ordering::SeqCst), Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_tasks_then_flushes() {
+        let recorder = Arc::new(Recorder::default());
+        let publisher =
+            Publisher::new(vec![recorder.clone()], EqualSamples::default(), None).unwrap();
+        let client = client::new(None, &Tls::default()).unwrap();
+        let mirror = Mirror::new(publisher, client.clone(), client);
+
+        let task_finished = recorder.task_finished.clone();
+        mirror.tasks.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            task_finished.store(true, Ordering::SeqCst);
+        });
+
+        mirror
+            .shutdown(tokio::time::Instant::now() + Duration::from_secs(5))
+            .await;
+
+        assert!(recorder.task_finished.load(Ordering::SeqCst));
+        assert_eq!(recorder.flushes.load(Ordering::SeqCst), 1);
+        assert!(recorder.finished_before_flush.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_on_panic() {
+        let before = metrics::MIRROR_TASKS_IN_FLIGHT.get();
+
+        let in_flight = InFlight::new();
+        assert_eq!(metrics::MIRROR_TASKS_IN_FLIGHT.get(), before + 1);
+        let task = tokio::spawn(async move {
+            let _in_flight = in_flight;
+            panic!("mirroring failed");
+        });
+
+        assert!(task.await.unwrap_err().is_panic());
+        assert_eq!(metrics::MIRROR_TASKS_IN_FLIGHT.get(), before);
     }
 }
@@ -1,5 +1,12 @@
+pub mod comparison;
 pub mod dispatcher;
-mod error;
+pub mod envelope;
+pub mod error;
 pub mod mirror;
+pub mod predicate;
 pub mod publisher;
+pub mod redact;
+pub mod rewrite;
+pub mod sampling;
+pub mod sink;
 pub mod tx_ext;
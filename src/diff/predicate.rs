@@ -0,0 +1,190 @@
+use crate::diff::error::InvalidConfig;
+use crate::settings::{Conditions, Route};
+use http::{HeaderName, Method, Request};
+
+/// a value to match, `*` matches any value
+#[derive(Debug, Clone)]
+enum Expected {
+    Any,
+    Exactly(String),
+}
+
+impl Expected {
+    fn new(value: &str) -> Self {
+        if value == "*" {
+            Expected::Any
+        } else {
+            Expected::Exactly(value.to_string())
+        }
+    }
+
+    fn matches(&self, actual: &str) -> bool {
+        match self {
+            Expected::Any => true,
+            Expected::Exactly(expected) => expected == actual,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Matcher {
+    headers: Vec<(HeaderName, Expected)>,
+    query: Vec<(String, Expected)>,
+}
+
+impl Matcher {
+    fn new(route: &Route, conditions: &Conditions) -> Result<Self, InvalidConfig> {
+        let headers = conditions
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::try_from(name.as_str())
+                    .map_err(|_| InvalidConfig::HeaderName(route.path.clone(), name.clone()))?;
+                Ok((name, Expected::new(value)))
+            })
+            .collect::<Result<_, _>>()?;
+        let query = conditions
+            .query
+            .iter()
+            .map(|(name, value)| (name.clone(), Expected::new(value)))
+            .collect();
+
+        Ok(Self { headers, query })
+    }
+
+    /// iterate over the conditions, checking if each one is met
+    fn results<'a, B>(&'a self, req: &'a Request<B>) -> impl Iterator<Item = bool> + 'a {
+        let query: Vec<(String, String)> =
+            form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+
+        let headers = self.headers.iter().map(|(name, expected)| {
+            req.headers()
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .any(|v| expected.matches(v))
+        });
+        let params = self.query.iter().map(move |(name, expected)| {
+            query.iter().any(|(k, v)| k == name && expected.matches(v))
+        });
+
+        headers.chain(params)
+    }
+}
+
+/// decides if a request matching a route's path is relevant for the experiment, by method, headers and query
+#[derive(Debug, Clone, Default)]
+pub struct Predicate {
+    /// allowed methods, any method if empty
+    methods: Vec<Method>,
+    /// all conditions must be met
+    require: Matcher,
+    /// no condition may be met
+    exclude: Matcher,
+}
+
+impl Predicate {
+    pub fn new(route: &Route) -> Result<Self, InvalidConfig> {
+        let methods = route
+            .methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| InvalidConfig::Method(route.path.clone(), m.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            methods,
+            require: Matcher::new(route, &route.require)?,
+            exclude: Matcher::new(route, &route.exclude)?,
+        })
+    }
+
+    /// check if the request meets all conditions
+    pub fn matches<B>(&self, req: &Request<B>) -> bool {
+        (self.methods.is_empty() || self.methods.contains(req.method()))
+            && self.require.results(req).all(|met| met)
+            && !self.exclude.results(req).any(|met| met)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Predicate;
+    use crate::settings;
+    use bytes::Bytes;
+
+    fn predicate(route: &str) -> Predicate {
+        let config = settings::from_toml(&format!(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{route}]
+            "#
+        ));
+        Predicate::new(&config.routes[0]).unwrap()
+    }
+
+    fn request(method: &str, uri: &str, headers: &[(&str, &str)]) -> http::Request<Bytes> {
+        let mut builder = http::Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Bytes::new()).unwrap()
+    }
+
+    #[test]
+    fn test_methods() {
+        let predicate = predicate(r#"{ path = "/user/{id}", methods = ["get", "HEAD"] }"#);
+
+        assert!(predicate.matches(&request("GET", "/user/1", &[])));
+        assert!(predicate.matches(&request("HEAD", "/user/1", &[])));
+        assert!(!predicate.matches(&request("DELETE", "/user/1", &[])));
+    }
+
+    #[test]
+    fn test_require() {
+        let predicate = predicate(
+            r#"{ path = "/user/{id}", require = { headers = { X-Tenant = "beta" }, query = { preview = "*" } } }"#,
+        );
+
+        assert!(predicate.matches(&request(
+            "GET",
+            "/user/1?preview=yes%21",
+            &[("x-tenant", "beta")]
+        )));
+        assert!(!predicate.matches(&request("GET", "/user/1?preview", &[("x-tenant", "alpha")])));
+        assert!(!predicate.matches(&request("GET", "/user/1", &[("x-tenant", "beta")])));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let predicate = predicate(
+            r#"{ path = "/user/{id}", exclude = { headers = { x-debug = "*" }, query = { dry-run = "true" } } }"#,
+        );
+
+        assert!(predicate.matches(&request("GET", "/user/1?dry-run=false", &[])));
+        assert!(!predicate.matches(&request("GET", "/user/1?dry-run=true", &[])));
+        assert!(!predicate.matches(&request("GET", "/user/1", &[("x-debug", "1")])));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let config = settings::from_toml(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/a", require = { headers = { "x tenant" = "beta" } } }]
+            "#,
+        );
+
+        assert_eq!(
+            Predicate::new(&config.routes[0]).unwrap_err().to_string(),
+            "invalid header-name for route /a: x tenant"
+        );
+    }
+}
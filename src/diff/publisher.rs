@@ -1,48 +1,249 @@
-use crate::domain;
-use crate::settings::Kafka;
-use rdkafka::ClientConfig;
-use rdkafka::producer::FutureRecord;
+use crate::diff::envelope::{self, CloudEvents};
+use crate::diff::error::InvalidConfig;
+use crate::diff::sink::Sink;
+use crate::settings::{self, EqualSamples};
+use crate::{domain, metrics};
+use serde::Serialize;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::{debug, info};
+use tracing::{error, info};
 
 #[derive(Clone)]
 pub struct Publisher {
-    topic: String,
-    producer: rdkafka::producer::FutureProducer,
+    sinks: Vec<Arc<dyn Sink>>,
+    /// which samples of equal responses to publish
+    equal_samples: EqualSamples,
+    /// if set, wrap samples in a CloudEvents-envelope
+    cloudevents: Option<CloudEvents>,
 }
 
 impl Publisher {
-    pub fn new(config: Kafka, properties: Vec<(String, String)>) -> Self {
-        let mut cfg = ClientConfig::new();
-        cfg.extend(config.properties.into_iter().map(|(k, v)| (k, v.into())));
-        cfg.extend(properties);
-
-        let producer = cfg.create().expect("invalid kafka configuration");
-
-        Self {
-            topic: config.topic,
-            producer,
+    pub fn new(
+        sinks: Vec<Arc<dyn Sink>>,
+        equal_samples: EqualSamples,
+        cloudevents: Option<&settings::CloudEvents>,
+    ) -> Result<Self, InvalidConfig> {
+        if !(0.0..=1.0).contains(&equal_samples.rate) {
+            return Err(InvalidConfig::EqualSampleRate(equal_samples.rate));
         }
+
+        Ok(Self {
+            sinks,
+            equal_samples,
+            cloudevents: cloudevents.map(CloudEvents::new),
+        })
     }
 
     pub async fn publish(&self, key: &str, sample: domain::Sample) {
-        if sample.is_equal() {
+        let result = if sample.is_equal() {
+            "equal"
+        } else {
+            "different"
+        };
+        metrics::SAMPLES
+            .with_label_values(&[
+                sample.request.route.as_str(),
+                sample.candidate_name.as_deref().unwrap_or_default(),
+                result,
+            ])
+            .inc();
+
+        let sample = if !sample.is_equal() {
+            sample
+        } else if rand::random::<f64>() < self.equal_samples.rate {
+            // a published equal sample stands for all equal samples that were not
+            sample.with_weight(1.0 / self.equal_samples.rate)
+        } else {
             info!(
                 "request to {} {} equals reference from {} to, not sending message",
                 sample.request.method, sample.candidate.url, sample.reference.url
             );
             return;
+        };
+
+        let message = if sample.is_equal() && self.equal_samples.compact {
+            self.serialize(&sample, envelope::MATCH, &sample.to_match())
+        } else {
+            self.serialize(&sample, envelope::SAMPLE, &sample.to_record())
+        };
+        let message = message.expect("failed to serialize sample-message");
+
+        for sink in &self.sinks {
+            if let Err(e) = sink.publish(key, &sample, &message).await {
+                error!("error publishing sample: {e}");
+            }
         }
+    }
+
+    /// serialize the data (derived from the sample), wrapped in an envelope if configured
+    fn serialize<T: Serialize>(
+        &self,
+        sample: &domain::Sample,
+        event_type: &'static str,
+        data: &T,
+    ) -> serde_json::Result<String> {
+        match &self.cloudevents {
+            Some(cloudevents) => cloudevents.wrap(sample, event_type, data),
+            None => serde_json::to_string(data),
+        }
+    }
+
+    /// flush all sinks, i.e. wait (at most `timeout`) until pending samples are delivered
+    pub async fn flush(&self, timeout: Duration) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.flush(timeout).await {
+                error!("error flushing sink: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Publisher;
+    use crate::diff::comparison::Comparison;
+    use crate::diff::sink::{BoxFuture, Error, Sink};
+    use crate::domain::{Request, RequestResult, Sample};
+    use crate::settings::{self, EqualSamples};
+    use bytes::Bytes;
+    use std::sync::{Arc, Mutex};
+
+    /// sink recording all published samples
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl Sink for Recorder {
+        fn publish<'a>(
+            &'a self,
+            key: &'a str,
+            _sample: &'a Sample,
+            payload: &'a str,
+        ) -> BoxFuture<'a, Result<(), Error>> {
+            let value = serde_json::from_str(payload).unwrap();
+            self.0.lock().unwrap().push((key.to_string(), value));
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn sample(reference: &'static str, candidate: &'static str) -> Sample {
+        let request = http::Request::new(Bytes::new());
+        let response = |body| {
+            let response = http::Response::builder()
+                .header("Content-Type", "application/json")
+                .body(Bytes::from_static(body))
+                .unwrap();
+            Ok(response.into())
+        };
+
+        Sample::new(
+            Request::new(
+                &request,
+                "/api/{value}".to_string(),
+                vec![],
+                http::HeaderMap::new(),
+            ),
+            RequestResult::new("reference".to_string(), response(reference.as_bytes())),
+            RequestResult::new("candidate".to_string(), response(candidate.as_bytes())),
+            None,
+            &Comparison::default(),
+            vec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_publish_different_samples_only() {
+        let recorder = Arc::new(Recorder::default());
+        let publisher =
+            Publisher::new(vec![recorder.clone()], EqualSamples::default(), None).unwrap();
+
+        publisher.publish("equal", sample("1", "1")).await;
+        publisher.publish("different", sample("1", "2")).await;
+
+        let published = recorder.0.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].0, "different");
+        assert_eq!(published[0].1["differences"], serde_json::json!(["body"]));
+    }
+
+    #[tokio::test]
+    async fn test_publish_equal_samples() {
+        let recorder = Arc::new(Recorder::default());
+        let equal_samples = |compact| EqualSamples {
+            rate: 1.0,
+            compact,
+            topic: None,
+        };
+
+        let publisher = Publisher::new(vec![recorder.clone()], equal_samples(false), None).unwrap();
+        publisher.publish("equal", sample("1", "1")).await;
+        let publisher = Publisher::new(vec![recorder.clone()], equal_samples(true), None).unwrap();
+        publisher.publish("compact", sample("1", "1")).await;
+        publisher.publish("different", sample("1", "2")).await;
+
+        let published = recorder.0.lock().unwrap();
+        assert_eq!(published.len(), 3);
+        assert_eq!(published[0].1["equal"], true);
+        assert_eq!(published[0].1["reference"]["url"], "reference");
+        assert_eq!(
+            published[1].1,
+            serde_json::json!({"equal": true, "weight": 1.0, "method": "GET", "route": "/api/{value}", "differences": []})
+        );
+        // different samples are published completely
+        assert_eq!(published[2].1["equal"], false);
+        assert_eq!(published[2].1["candidate"]["url"], "candidate");
+
+        let invalid = EqualSamples {
+            rate: 1.5,
+            ..Default::default()
+        };
+        assert!(Publisher::new(vec![], invalid, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_weight_of_equal_samples() {
+        let recorder = Arc::new(Recorder::default());
+        let equal_samples = EqualSamples {
+            rate: 0.25,
+            ..Default::default()
+        };
+        let publisher = Publisher::new(vec![recorder.clone()], equal_samples, None).unwrap();
+
+        for _ in 0..100 {
+            publisher.publish("equal", sample("1", "1")).await;
+        }
+        publisher.publish("different", sample("1", "2")).await;
+
+        let published = recorder.0.lock().unwrap();
+        let (different, equal) = published.split_last().unwrap();
+        assert!(!equal.is_empty() && equal.len() < 100);
+        assert!(equal.iter().all(|(_, sample)| sample["weight"] == 4.0));
+        assert_eq!(different.1["weight"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_publish_cloudevents() {
+        let recorder = Arc::new(Recorder::default());
+        let cloudevents = settings::CloudEvents {
+            source: "/miffy/test".to_string(),
+        };
+        let publisher = Publisher::new(
+            vec![recorder.clone()],
+            EqualSamples::default(),
+            Some(&cloudevents),
+        )
+        .unwrap();
 
-        let message = serde_json::to_string(&sample).expect("failed to serialize sample-message");
+        publisher.publish("different", sample("1", "2")).await;
 
-        let delivery_status = self
-            .producer
-            .send::<_, _, _>(
-                FutureRecord::to(&self.topic).key(key).payload(&message),
-                Duration::from_secs(0),
-            )
-            .await;
-        debug!("Delivery status: {delivery_status:?}");
+        let published = recorder.0.lock().unwrap();
+        let event = &published[0].1;
+        assert_eq!(event["specversion"], "1.0");
+        assert_eq!(event["type"], "miffy.sample");
+        assert_eq!(event["source"], "/miffy/test");
+        assert_eq!(event["subject"], "/api/{value}");
+        assert_eq!(event["id"].as_str().unwrap().len(), 32);
+        assert!(event["time"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(event["data"]["differences"], serde_json::json!(["body"]));
     }
 }
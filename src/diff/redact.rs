@@ -0,0 +1,380 @@
+use crate::diff::error::InvalidConfig;
+use crate::domain::Body;
+use crate::settings::{Config, Pattern, Redaction, RequestHeaders, Route};
+use crate::util::json_path::JsonPath;
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use regex::Regex;
+use ring::digest;
+use serde_json::Value;
+use std::fmt::Write;
+
+const MASK: &str = "***";
+
+/// the redacted value, None if it's dropped
+pub fn redact(redaction: Redaction, value: &[u8]) -> Option<String> {
+    match redaction {
+        Redaction::Replace => Some(MASK.to_string()),
+        Redaction::Hash => Some(hash(value)),
+        Redaction::Drop => None,
+    }
+}
+
+/// SHA-256 of the value, hex-encoded
+fn hash(value: &[u8]) -> String {
+    digest::digest(&digest::SHA256, value).as_ref().iter().fold(
+        String::from("sha256:"),
+        |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        },
+    )
+}
+
+/// which request-headers to include in samples, and how to redact them
+#[derive(Debug)]
+pub struct HeaderCapture {
+    capture: bool,
+    capture_sent: bool,
+    redaction: Redaction,
+    redact: Vec<HeaderName>,
+}
+
+impl HeaderCapture {
+    pub fn new(settings: &RequestHeaders) -> Result<Self, InvalidConfig> {
+        let redact = settings
+            .redact
+            .iter()
+            .map(|name| {
+                HeaderName::try_from(name).map_err(|_| InvalidConfig::RedactHeader(name.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            capture: settings.capture,
+            capture_sent: settings.capture_sent,
+            redaction: settings.redaction,
+            redact,
+        })
+    }
+
+    /// if the headers as sent to reference and candidate are captured
+    pub fn captures_sent(&self) -> bool {
+        self.capture_sent
+    }
+
+    /// the (redacted) headers of the original request to include in the sample, empty if not captured
+    pub fn original(&self, headers: &HeaderMap) -> HeaderMap {
+        if self.capture {
+            self.redacted(headers.clone())
+        } else {
+            HeaderMap::new()
+        }
+    }
+
+    /// the (redacted) headers as sent to an upstream, None if not captured
+    pub fn sent(&self, headers: Option<HeaderMap>) -> Option<HeaderMap> {
+        headers
+            .filter(|_| self.capture_sent)
+            .map(|headers| self.redacted(headers))
+    }
+
+    fn redacted(&self, mut headers: HeaderMap) -> HeaderMap {
+        for name in &self.redact {
+            let values: Vec<HeaderValue> = headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| redact(self.redaction, value.as_bytes()))
+                .filter_map(|value| HeaderValue::try_from(value).ok())
+                .collect();
+
+            // removes all values
+            headers.remove(name);
+            for value in values {
+                headers.append(name, value);
+            }
+        }
+
+        headers
+    }
+}
+
+impl Pattern {
+    fn regex(self) -> &'static str {
+        match self {
+            Pattern::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+            Pattern::Iban => r"\b[A-Z]{2}[0-9]{2}(?: ?[A-Z0-9]){11,30}\b",
+            Pattern::Jwt => r"\beyJ[A-Za-z0-9_-]*\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]*",
+        }
+    }
+}
+
+/// values to redact from the bodies of a route
+#[derive(Debug)]
+pub struct BodyRedaction {
+    paths: Vec<JsonPath>,
+    patterns: Vec<Regex>,
+    redaction: Redaction,
+}
+
+impl BodyRedaction {
+    /// the global rules combined with the rules of the route, None if there's nothing to redact
+    pub fn new(config: &Config, route: &Route) -> Result<Option<Self>, InvalidConfig> {
+        let (global, local) = (&config.redact_body, &route.redact_body);
+
+        let paths: Vec<_> = global.paths.iter().chain(&local.paths).cloned().collect();
+        let patterns = global
+            .patterns
+            .iter()
+            .chain(&local.patterns)
+            .map(|p| p.regex())
+            .chain(global.regex.iter().chain(&local.regex).map(String::as_str))
+            .map(|regex| {
+                Regex::new(regex).map_err(|e| InvalidConfig::RedactPattern(route.path.clone(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if paths.is_empty() && patterns.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            paths,
+            patterns,
+            redaction: local.redaction.or(global.redaction).unwrap_or_default(),
+        }))
+    }
+
+    /// redact the body in place
+    pub fn apply(&self, body: &mut Body) {
+        match body {
+            Body::Json(value) => {
+                for path in &self.paths {
+                    if self.redaction == Redaction::Drop {
+                        path.remove(value);
+                    } else {
+                        path.for_each_mut(value, |v| *v = self.redact_value(v));
+                    }
+                }
+                self.redact_strings(value);
+            }
+            Body::Bytes(bytes) => {
+                if let Ok(text) = std::str::from_utf8(bytes) {
+                    if let Some(redacted) = self.redact_text(text) {
+                        *bytes = Bytes::from(redacted);
+                    }
+                }
+            }
+            Body::None => {}
+        }
+    }
+
+    /// the redacted JSON-value: strings are hashed as is, other values as JSON
+    fn redact_value(&self, value: &Value) -> Value {
+        let redacted = match value {
+            Value::String(s) => redact(self.redaction, s.as_bytes()),
+            _ => redact(self.redaction, value.to_string().as_bytes()),
+        };
+        redacted.map_or(Value::Null, Value::String)
+    }
+
+    /// redact matches of the patterns in all strings of the value
+    fn redact_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(redacted) = self.redact_text(s) {
+                    *s = redacted;
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_strings(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_strings(v)),
+            _ => {}
+        }
+    }
+
+    /// the text with all matches of the patterns redacted, None if nothing matched
+    fn redact_text(&self, text: &str) -> Option<String> {
+        let mut result: Option<String> = None;
+        for pattern in &self.patterns {
+            let current = result.as_deref().unwrap_or(text);
+            if pattern.is_match(current) {
+                let redacted = pattern
+                    .replace_all(current, |caps: &regex::Captures| {
+                        redact(self.redaction, caps[0].as_bytes()).unwrap_or_default()
+                    })
+                    .into_owned();
+                result = Some(redacted);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{BodyRedaction, HeaderCapture};
+    use crate::domain::Body;
+    use crate::settings::{self, Redaction};
+    use bytes::Bytes;
+    use http::{HeaderMap, HeaderValue};
+    use serde_json::json;
+
+    fn capture(redaction: Redaction) -> HeaderCapture {
+        let mut settings = settings::from_toml(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = []
+            "#,
+        )
+        .request_headers;
+        settings.redaction = redaction;
+        settings.capture = true;
+        settings.capture_sent = true;
+        HeaderCapture::new(&settings).unwrap()
+    }
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("accept-language", HeaderValue::from_static("de"));
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.append("cookie", HeaderValue::from_static("a=1"));
+        headers.append("cookie", HeaderValue::from_static("b=2"));
+        headers
+    }
+
+    #[test]
+    fn test_mask() {
+        let actual = capture(Redaction::Replace).original(&headers());
+
+        assert_eq!(actual.get("accept-language").unwrap(), "de");
+        assert_eq!(actual.get("authorization").unwrap(), "***");
+        assert_eq!(actual.get_all("cookie").iter().count(), 2);
+        assert!(actual.get_all("cookie").iter().all(|v| v == "***"));
+    }
+
+    #[test]
+    fn test_hash() {
+        let actual = capture(Redaction::Hash).sent(Some(headers())).unwrap();
+
+        assert_eq!(
+            actual.get("authorization").unwrap(),
+            "sha256:bffde20413347b7a00e1363de3f97ca69e419dc0aea55f4a4a75018fab3a0e8e"
+        );
+    }
+
+    #[test]
+    fn test_drop() {
+        let actual = capture(Redaction::Drop).original(&headers());
+
+        assert_eq!(actual.get("accept-language").unwrap(), "de");
+        assert!(!actual.contains_key("authorization"));
+        assert!(!actual.contains_key("cookie"));
+    }
+
+    fn body_redaction(redaction: &str) -> BodyRedaction {
+        let config = settings::from_toml(&format!(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            redact_body = {{ paths = ["$..iban"], patterns = ["email"] }}
+            routes = [{{ path = "/user", redact_body = {{ regex = ["tok_[a-z0-9]+"], redaction = "{redaction}" }} }}]
+            "#
+        ));
+        BodyRedaction::new(&config, &config.routes[0])
+            .unwrap()
+            .unwrap()
+    }
+
+    fn json_body() -> Body {
+        Body::Json(json!({
+            "account": {"iban": "DE89370400440532013000", "owner": "Jane"},
+            "contact": "mail jane@example.com or john@example.org",
+            "token": "tok_abc123",
+            "count": 2,
+        }))
+    }
+
+    #[test]
+    fn test_body_mask() {
+        let mut body = json_body();
+        body_redaction("mask").apply(&mut body);
+
+        assert_eq!(
+            body,
+            Body::Json(json!({
+                "account": {"iban": "***", "owner": "Jane"},
+                "contact": "mail *** or ***",
+                "token": "***",
+                "count": 2,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_body_hash() {
+        let redaction = body_redaction("hash");
+        let (mut a, mut b) = (json_body(), json_body());
+        redaction.apply(&mut a);
+        redaction.apply(&mut b);
+
+        // equal values stay equal
+        assert_eq!(a, b);
+        let Body::Json(value) = a else {
+            panic!("not JSON")
+        };
+        assert!(
+            value["account"]["iban"]
+                .as_str()
+                .unwrap()
+                .starts_with("sha256:")
+        );
+        assert!(!value["contact"].as_str().unwrap().contains("example"));
+    }
+
+    #[test]
+    fn test_body_drop() {
+        let mut body = json_body();
+        body_redaction("drop").apply(&mut body);
+
+        assert_eq!(
+            body,
+            Body::Json(json!({
+                "account": {"owner": "Jane"},
+                "contact": "mail  or ",
+                "token": "",
+                "count": 2,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_body_text() {
+        let mut body = Body::Bytes(Bytes::from("email=jane@example.com&token=tok_abc123"));
+        body_redaction("mask").apply(&mut body);
+        assert_eq!(body, Body::Bytes(Bytes::from("email=***&token=***")));
+
+        // binary bodies are kept
+        let mut body = Body::Bytes(Bytes::from_static(&[0xff, 0xfe, 0x40]));
+        body_redaction("mask").apply(&mut body);
+        assert_eq!(body, Body::Bytes(Bytes::from_static(&[0xff, 0xfe, 0x40])));
+    }
+
+    #[test]
+    fn test_body_nothing_to_redact() {
+        let config = settings::from_toml(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/user" }, { path = "/invalid", redact_body = { regex = ["("] } }]
+            "#,
+        );
+        assert!(
+            BodyRedaction::new(&config, &config.routes[0])
+                .unwrap()
+                .is_none()
+        );
+        assert!(BodyRedaction::new(&config, &config.routes[1]).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::diff::error::InvalidConfig;
+use crate::settings::Route;
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+/// a part of a path-template
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// name of a route-parameter
+    Param(String),
+}
+
+/// rewrites requests to the candidate(s) of a route: path, query and headers
+#[derive(Debug, Clone, Default)]
+pub struct Rewrite {
+    path: Option<Vec<Segment>>,
+    query_add: Vec<(String, String)>,
+    query_remove: Vec<String>,
+    headers_set: Vec<(HeaderName, HeaderValue)>,
+    headers_replace: Vec<(HeaderName, HeaderValue)>,
+    headers_remove: Vec<HeaderName>,
+}
+
+impl Rewrite {
+    /// compile the rewrite-rules of the route, None if there are none
+    pub fn new(route: &Route) -> Result<Option<Self>, InvalidConfig> {
+        let rewrite = &route.rewrite;
+        let header_name = |name: &String| {
+            HeaderName::try_from(name)
+                .map_err(|_| InvalidConfig::HeaderName(route.path.clone(), name.clone()))
+        };
+        let header = |(name, value): (&String, &String)| {
+            let value = HeaderValue::try_from(value)
+                .map_err(|_| InvalidConfig::HeaderValue(route.path.clone(), name.clone()))?;
+            Ok((header_name(name)?, value))
+        };
+
+        let result = Self {
+            path: rewrite
+                .path
+                .as_ref()
+                .map(|template| parse(route, template))
+                .transpose()?,
+            query_add: rewrite
+                .query
+                .add
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            query_remove: rewrite.query.remove.clone(),
+            headers_set: rewrite
+                .headers
+                .set
+                .iter()
+                .map(header)
+                .collect::<Result<_, _>>()?,
+            headers_replace: rewrite
+                .headers
+                .replace
+                .iter()
+                .map(header)
+                .collect::<Result<_, _>>()?,
+            headers_remove: rewrite
+                .headers
+                .remove
+                .iter()
+                .map(header_name)
+                .collect::<Result<_, _>>()?,
+        };
+
+        let is_empty = result.path.is_none()
+            && result.query_add.is_empty()
+            && result.query_remove.is_empty()
+            && result.headers_set.is_empty()
+            && result.headers_replace.is_empty()
+            && result.headers_remove.is_empty();
+        Ok((!is_empty).then_some(result))
+    }
+
+    /// rewrite path and query of the original request, using the parameters extracted from the route
+    pub fn path_query(&self, path_query: &str, params: &[(String, String)]) -> String {
+        let (path, query) = match path_query.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path_query, None),
+        };
+
+        let path = match &self.path {
+            Some(segments) => segments
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Literal(literal) => literal.as_str(),
+                    Segment::Param(name) => params
+                        .iter()
+                        .find(|(k, _)| k == name)
+                        .map_or("", |(_, v)| v.as_str()),
+                })
+                .collect(),
+            None => path.to_string(),
+        };
+
+        match self.query(query) {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        }
+    }
+
+    /// the original query, if no query-parameters are added or removed
+    fn query(&self, query: Option<&str>) -> Option<String> {
+        if self.query_add.is_empty() && self.query_remove.is_empty() {
+            return query.map(str::to_string);
+        }
+
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .filter(|(name, _)| {
+                !self.query_remove.iter().any(|n| n == name)
+                    && !self.query_add.iter().any(|(n, _)| n == name)
+            })
+            .for_each(|(name, value)| {
+                serializer.append_pair(&name, &value);
+            });
+        serializer.extend_pairs(&self.query_add);
+
+        Some(serializer.finish()).filter(|query| !query.is_empty())
+    }
+
+    /// rewrite the headers of the request: remove, replace, then set
+    pub fn headers(&self, headers: &mut HeaderMap) {
+        for name in &self.headers_remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.headers_replace {
+            if headers.contains_key(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        for (name, value) in &self.headers_set {
+            headers.insert(name, value.clone());
+        }
+    }
+}
+
+/// parse a path-template, all parameters must be defined by the route
+fn parse(route: &Route, template: &str) -> Result<Vec<Segment>, InvalidConfig> {
+    let mut segments = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| InvalidConfig::RewritePath(route.path.clone(), template.to_string()))?;
+        let name = &rest[start + 1..end];
+        if !route.path.contains(&format!("{{{name}}}"))
+            && !route.path.contains(&format!("{{*{name}}}"))
+        {
+            return Err(InvalidConfig::UnknownParameter(
+                route.path.clone(),
+                name.to_string(),
+            ));
+        }
+
+        if start > 0 {
+            segments.push(Segment::Literal(rest[..start].to_string()));
+        }
+        segments.push(Segment::Param(name.to_string()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.to_string()));
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Rewrite;
+    use crate::diff::error::InvalidConfig;
+    use crate::settings;
+    use http::{HeaderMap, HeaderValue};
+
+    fn rewrite(route: &str) -> Result<Option<Rewrite>, InvalidConfig> {
+        let config = settings::from_toml(&format!(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{route}]
+            "#
+        ));
+        Rewrite::new(&config.routes[0])
+    }
+
+    fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(rewrite(r#"{ path = "/user/{id}" }"#).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_path_query() {
+        let rewrite = rewrite(
+            r#"{ path = "/user/{id}/{*rest}", rewrite = { path = "/v2/users/{id}/{rest}.json", query = { add = { version = "2" }, remove = ["legacy"] } } }"#,
+        )
+        .unwrap()
+        .expect("rewrite must be configured");
+        let params = params(&[("id", "42"), ("rest", "a/b")]);
+
+        assert_eq!(
+            rewrite.path_query("/user/42/a/b?legacy=1&version=1&q=a+b", &params),
+            "/v2/users/42/a/b.json?q=a+b&version=2"
+        );
+        assert_eq!(
+            rewrite.path_query("/user/42/a/b", &params),
+            "/v2/users/42/a/b.json?version=2"
+        );
+    }
+
+    #[test]
+    fn test_keep_query() {
+        let rewrite = rewrite(r#"{ path = "/user/{id}", rewrite = { path = "/v2/{id}" } }"#)
+            .unwrap()
+            .expect("rewrite must be configured");
+
+        assert_eq!(
+            rewrite.path_query("/user/1?a=%20", &params(&[("id", "1")])),
+            "/v2/1?a=%20"
+        );
+    }
+
+    #[test]
+    fn test_headers() {
+        let rewrite = rewrite(
+            r#"{ path = "/user/{id}", rewrite = { headers = { set = { authorization = "Bearer candidate" }, replace = { x-tenant = "test", x-absent = "1" }, remove = ["cookie"] } } }"#,
+        )
+        .unwrap()
+        .expect("rewrite must be configured");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer reference"),
+        );
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+        headers.insert("x-tenant", HeaderValue::from_static("prod"));
+        rewrite.headers(&mut headers);
+
+        assert_eq!(
+            headers.get("authorization"),
+            Some(&HeaderValue::from_static("Bearer candidate"))
+        );
+        assert_eq!(
+            headers.get("x-tenant"),
+            Some(&HeaderValue::from_static("test"))
+        );
+        assert!(!headers.contains_key("cookie"));
+        assert!(!headers.contains_key("x-absent"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |route| rewrite(route).unwrap_err().to_string();
+
+        assert_eq!(
+            error(r#"{ path = "/user/{id}", rewrite = { path = "/v2/{name}" } }"#),
+            "unknown parameter in rewrite-path for route /user/{id}: name"
+        );
+        assert_eq!(
+            error(r#"{ path = "/user/{id}", rewrite = { path = "/v2/{id" } }"#),
+            "invalid rewrite-path for route /user/{id}: /v2/{id"
+        );
+        assert_eq!(
+            error(r#"{ path = "/a", rewrite = { headers = { set = { x-a = "\n" } } } }"#),
+            "invalid header-value for route /a: x-a"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::diff::error::InvalidConfig;
+use crate::settings::SampleBy;
+use http::HeaderMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// decides if a request matching a route is mirrored
+#[derive(Debug)]
+pub struct Sampling {
+    /// fraction of requests to mirror, 0.0–1.0 (as bits of the f64), may be changed at runtime
+    rate: AtomicU64,
+    /// the rate was changed at runtime, i.e. differs from the configured one
+    overridden: AtomicBool,
+    /// if set, derive the decision from this value instead of randomly
+    by: Option<SampleBy>,
+}
+
+/// FNV-1a (with murmur3's finalizer to spread short values evenly), a simple hash that is stable across versions and instances
+fn hash(value: &[u8]) -> u64 {
+    let mut hash = value.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+impl Sampling {
+    pub fn new(rate: f64, by: Option<SampleBy>) -> Result<Self, InvalidConfig> {
+        Self::validate(rate)?;
+
+        Ok(Self {
+            rate: AtomicU64::new(rate.to_bits()),
+            overridden: AtomicBool::new(false),
+            by,
+        })
+    }
+
+    fn validate(rate: f64) -> Result<(), InvalidConfig> {
+        if (0.0..=1.0).contains(&rate) {
+            Ok(())
+        } else {
+            Err(InvalidConfig::SampleRate(rate))
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    /// change the sample-rate at runtime
+    pub fn set_rate(&self, rate: f64) -> Result<(), InvalidConfig> {
+        Self::validate(rate)?;
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+        self.overridden.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// the sample-rate, if changed at runtime
+    pub fn overridden_rate(&self) -> Option<f64> {
+        self.overridden.load(Ordering::Relaxed).then(|| self.rate())
+    }
+
+    /// decide if the request is sampled, i.e. mirrored
+    pub fn is_sampled(&self, params: &matchit::Params, headers: &HeaderMap) -> bool {
+        let rate = self.rate();
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let value = match &self.by {
+            Some(SampleBy::Param(name)) => params.get(name).map(str::as_bytes),
+            Some(SampleBy::Header(name)) => headers.get(name).map(http::HeaderValue::as_bytes),
+            None => None,
+        };
+
+        // fall back to random sampling if the value is not available
+        let position = value.map_or_else(rand::random::<f64>, |value| {
+            #[expect(clippy::cast_precision_loss)]
+            let position = hash(value) as f64 / u64::MAX as f64;
+            position
+        });
+
+        position < rate
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Sampling;
+    use crate::settings::SampleBy;
+    use http::{HeaderMap, HeaderValue};
+
+    fn router() -> matchit::Router<()> {
+        let mut router = matchit::Router::new();
+        router.insert("/user/{id}", ()).unwrap();
+        router
+    }
+
+    #[test]
+    fn test_sample_all_or_nothing() {
+        let router = router();
+        let params = router.at("/user/1").unwrap().params;
+        let headers = HeaderMap::new();
+
+        assert!(
+            Sampling::new(1.0, None)
+                .unwrap()
+                .is_sampled(&params, &headers)
+        );
+        assert!(
+            !Sampling::new(0.0, None)
+                .unwrap()
+                .is_sampled(&params, &headers)
+        );
+        assert!(Sampling::new(1.5, None).is_err());
+
+        let sampling = Sampling::new(0.0, None).unwrap();
+        assert_eq!(sampling.overridden_rate(), None);
+        sampling.set_rate(1.0).unwrap();
+        assert_eq!(sampling.overridden_rate(), Some(1.0));
+        assert!(sampling.is_sampled(&params, &headers));
+        assert!(sampling.set_rate(-0.1).is_err());
+        assert!((sampling.rate() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_sample_deterministic_by_header() {
+        let router = router();
+        let params = router.at("/user/1").unwrap().params;
+        let sampling = Sampling::new(0.5, Some(SampleBy::Header("x-user-id".to_string()))).unwrap();
+
+        let sampled = (0..100)
+            .map(|user| {
+                let mut headers = HeaderMap::new();
+                headers.insert("x-user-id", HeaderValue::from(user));
+
+                let first = sampling.is_sampled(&params, &headers);
+                // the same user is always sampled the same way
+                assert!((0..10).all(|_| sampling.is_sampled(&params, &headers) == first));
+                first
+            })
+            .filter(|sampled| *sampled)
+            .count();
+
+        assert!((30..70).contains(&sampled), "sampled {sampled} of 100");
+    }
+
+    #[test]
+    fn test_sample_deterministic_by_param() {
+        let router = router();
+        let headers = HeaderMap::new();
+        let sampling = Sampling::new(0.5, Some(SampleBy::Param("id".to_string()))).unwrap();
+
+        for user in 0..100 {
+            let path = format!("/user/{user}");
+            let params = router.at(&path).unwrap().params;
+
+            let first = sampling.is_sampled(&params, &headers);
+            assert!((0..10).all(|_| sampling.is_sampled(&params, &headers) == first));
+        }
+    }
+}
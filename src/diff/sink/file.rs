@@ -0,0 +1,51 @@
+use crate::diff::sink::{BoxFuture, Error, Sink};
+use crate::domain::Sample;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// append samples to a file, one JSON-document per line (JSONL)
+pub struct File {
+    path: PathBuf,
+    /// the file is opened lazily, on the first sample
+    file: Mutex<Option<tokio::fs::File>>,
+}
+
+impl File {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+}
+
+impl Sink for File {
+    fn publish<'a>(
+        &'a self,
+        _key: &'a str,
+        _sample: &'a Sample,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut file = self.file.lock().await;
+
+            let file = match &mut *file {
+                Some(file) => file,
+                None => file.insert(
+                    tokio::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.path)
+                        .await?,
+                ),
+            };
+
+            // write the line at once, so lines are never interleaved
+            file.write_all(format!("{payload}\n").as_bytes()).await?;
+            file.flush().await?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,125 @@
+use crate::diff::sink::{BoxFuture, Error, Sink};
+use crate::domain::{SCHEMA_VERSION, Sample};
+use crate::{metrics, settings};
+use rdkafka::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureRecord, Producer};
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::debug;
+
+/// how to publish, besides the rdkafka-properties
+pub struct Options {
+    /// topic for samples of equal responses, if not the default one
+    pub equal_topic: Option<String>,
+    /// identifies this instance, see `settings::Config::instance`
+    pub instance: String,
+    /// samples are wrapped in a CloudEvents-envelope
+    pub cloudevents: bool,
+}
+
+/// publish samples to a kafka-topic
+pub struct Kafka {
+    topic: String,
+    /// topic for samples of equal responses, if not `topic`
+    equal_topic: Option<String>,
+    /// headers added to all messages, in addition to the metadata of the sample
+    headers: Vec<(&'static str, String)>,
+    producer: rdkafka::producer::FutureProducer,
+}
+
+impl Kafka {
+    pub fn new(
+        config: settings::Kafka,
+        properties: Vec<(String, String)>,
+        options: Options,
+    ) -> Self {
+        let mut cfg = ClientConfig::new();
+        cfg.extend(config.properties.into_iter().map(|(k, v)| (k, v.into())));
+        cfg.extend(properties);
+
+        let producer = cfg.create().expect("invalid kafka configuration");
+
+        Self {
+            topic: config.topic,
+            equal_topic: options.equal_topic,
+            headers: vec![
+                ("miffy_version", env!("CARGO_PKG_VERSION").to_string()),
+                ("schema_version", SCHEMA_VERSION.to_string()),
+                ("instance", options.instance),
+                // see the kafka-binding of CloudEvents (structured mode)
+                (
+                    "content-type",
+                    if options.cloudevents {
+                        "application/cloudevents+json"
+                    } else {
+                        "application/json"
+                    }
+                    .to_string(),
+                ),
+            ],
+            producer,
+        }
+    }
+}
+
+impl Sink for Kafka {
+    fn publish<'a>(
+        &'a self,
+        key: &'a str,
+        sample: &'a Sample,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        let topic = match &self.equal_topic {
+            Some(equal_topic) if sample.is_equal() => equal_topic,
+            _ => &self.topic,
+        };
+
+        let headers = sample.metadata().iter().chain(&self.headers).fold(
+            OwnedHeaders::new(),
+            |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(value.as_str()),
+                })
+            },
+        );
+        let timestamp = sample
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|t| i64::try_from(t.as_millis()).ok());
+
+        Box::pin(async move {
+            let mut record = FutureRecord::to(topic)
+                .key(key)
+                .payload(payload)
+                .headers(headers);
+            if let Some(timestamp) = timestamp {
+                record = record.timestamp(timestamp);
+            }
+
+            let delivery_status = self
+                .producer
+                .send::<_, _, _>(record, Duration::from_secs(0))
+                .await;
+            debug!("Delivery status: {delivery_status:?}");
+
+            let result = if delivery_status.is_ok() {
+                "success"
+            } else {
+                "failure"
+            };
+            metrics::KAFKA_DELIVERIES.with_label_values(&[result]).inc();
+
+            delivery_status.map(|_| ()).map_err(|(e, _)| e.into())
+        })
+    }
+
+    fn flush(&self, timeout: Duration) -> BoxFuture<'_, Result<(), Error>> {
+        // flushing blocks until all messages are delivered (or the timeout expired)
+        let producer = self.producer.clone();
+        Box::pin(
+            async move { Ok(tokio::task::spawn_blocking(move || producer.flush(timeout)).await??) },
+        )
+    }
+}
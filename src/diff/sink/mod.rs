@@ -0,0 +1,213 @@
+use crate::diff::error::InvalidConfig;
+use crate::domain::Sample;
+use crate::http::error::Upstream;
+use crate::settings;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+pub mod file;
+pub mod kafka;
+pub mod stdout;
+pub mod webhook;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("error publishing to kafka: {0}")]
+    Kafka(#[from] rdkafka::error::KafkaError),
+
+    #[error("error writing sample: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("error sending sample to webhook: {0}")]
+    Webhook(#[from] Upstream),
+
+    #[error("webhook responded with unexpected status {0}")]
+    WebhookStatus(http::StatusCode),
+
+    #[error("error flushing: {0}")]
+    Flush(#[from] tokio::task::JoinError),
+}
+
+/// a destination for samples, e.g. kafka or a file
+pub trait Sink: Send + Sync {
+    /// publish a sample. `payload` is the sample (or its compact match-record), already serialized to JSON
+    fn publish<'a>(
+        &'a self,
+        key: &'a str,
+        sample: &'a Sample,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// wait (at most `timeout`) until all pending samples are delivered, e.g. on shutdown
+    fn flush(&self, _timeout: Duration) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// build all sinks as configured
+pub fn from_settings(
+    sinks: &[settings::Sink],
+    kafka: settings::Kafka,
+    kafka_properties: Vec<(String, String)>,
+    kafka_options: kafka::Options,
+) -> Result<Vec<Arc<dyn Sink>>, InvalidConfig> {
+    let mut kafka = Some((kafka, kafka_properties, kafka_options));
+
+    sinks
+        .iter()
+        .map(|sink| -> Result<Arc<dyn Sink>, InvalidConfig> {
+            Ok(match sink {
+                settings::Sink::Kafka => {
+                    let (config, properties, options) =
+                        kafka.take().ok_or(InvalidConfig::DuplicateSink("kafka"))?;
+                    Arc::new(kafka::Kafka::new(config, properties, options))
+                }
+                settings::Sink::Stdout => Arc::new(stdout::Stdout::new()),
+                settings::Sink::File { path } => Arc::new(file::File::new(path.clone())),
+                settings::Sink::Webhook { url, timeout } => {
+                    Arc::new(webhook::Webhook::new(url.clone(), *timeout))
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{Sink, file, webhook};
+    use crate::diff::comparison::Comparison;
+    use crate::diff::error::InvalidConfig;
+    use crate::domain::{Request, RequestResult, Sample};
+    use crate::settings;
+    use crate::util::temp_dir::TempDir;
+    use axum::Router;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use bytes::Bytes;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn sample() -> Sample {
+        let request = http::Request::new(Bytes::new());
+        let response = || Ok(http::Response::new(Bytes::from_static(b"1")).into());
+
+        Sample::new(
+            Request::new(&request, "/api".to_string(), vec![], http::HeaderMap::new()),
+            RequestResult::new("reference".to_string(), response()),
+            RequestResult::new("candidate".to_string(), response()),
+            None,
+            &Comparison::default(),
+            vec![],
+        )
+    }
+
+    /// serve `router` on a random local port, returning its URL
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{addr}/samples")
+    }
+
+    #[tokio::test]
+    async fn test_file() {
+        let dir = TempDir::new("sink-file");
+        let path = dir.join("samples.jsonl");
+        let sink = file::File::new(path.clone());
+        let sample = sample();
+
+        sink.publish("a", &sample, r#"{"n":1}"#).await.unwrap();
+        sink.publish("b", &sample, r#"{"n":2}"#).await.unwrap();
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![serde_json::json!({"n": 1}), serde_json::json!({"n": 2})]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let router = Router::new().route(
+            "/samples",
+            post({
+                let received = received.clone();
+                async move |headers: HeaderMap, body: String| {
+                    let key = headers["x-miffy-key"].to_str().unwrap().to_string();
+                    received.lock().unwrap().push((key, body));
+                    StatusCode::NO_CONTENT
+                }
+            }),
+        );
+        let sink = webhook::Webhook::new(serve(router).await, Duration::from_secs(5));
+
+        sink.publish("key", &sample(), r#"{"n":1}"#).await.unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("key".to_string(), r#"{"n":1}"#.to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_errors() {
+        let router = Router::new()
+            .route("/samples", post(async || StatusCode::INTERNAL_SERVER_ERROR))
+            .route(
+                "/slow",
+                post(async || tokio::time::sleep(Duration::from_secs(5)).await),
+            );
+        let url = serve(router).await;
+        let sample = sample();
+
+        let sink = webhook::Webhook::new(url.clone(), Duration::from_secs(5));
+        let actual = sink.publish("key", &sample, "{}").await.unwrap_err();
+        assert!(matches!(
+            actual,
+            super::Error::WebhookStatus(StatusCode::INTERNAL_SERVER_ERROR)
+        ));
+
+        let sink = webhook::Webhook::new(url.replace("samples", "slow"), Duration::from_millis(50));
+        let actual = sink.publish("key", &sample, "{}").await.unwrap_err();
+        assert!(matches!(actual, super::Error::Webhook(_)));
+    }
+
+    #[test]
+    fn test_duplicate_kafka_sink() {
+        let kafka = || settings::Kafka {
+            topic: "samples".to_string(),
+            properties: HashMap::new(),
+        };
+        let options = || super::kafka::Options {
+            equal_topic: None,
+            instance: "test".to_string(),
+            cloudevents: false,
+        };
+
+        let sinks = [settings::Sink::Kafka, settings::Sink::Stdout];
+        assert_eq!(
+            super::from_settings(&sinks, kafka(), vec![], options())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let sinks = [settings::Sink::Kafka, settings::Sink::Kafka];
+        assert!(matches!(
+            super::from_settings(&sinks, kafka(), vec![], options()),
+            Err(InvalidConfig::DuplicateSink("kafka"))
+        ));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::diff::sink::{BoxFuture, Error, Sink};
+use crate::domain::Sample;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// print samples to stdout, one JSON-document per line
+pub struct Stdout {
+    /// tasks publishing concurrently must not interleave their lines
+    stdout: Mutex<tokio::io::Stdout>,
+}
+
+impl Stdout {
+    pub fn new() -> Self {
+        Self {
+            stdout: Mutex::new(tokio::io::stdout()),
+        }
+    }
+}
+
+impl Sink for Stdout {
+    fn publish<'a>(
+        &'a self,
+        _key: &'a str,
+        _sample: &'a Sample,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut stdout = self.stdout.lock().await;
+            stdout.write_all(format!("{payload}\n").as_bytes()).await?;
+            stdout.flush().await?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use crate::diff::sink::{BoxFuture, Error, Sink};
+use crate::domain::Sample;
+use crate::http::client;
+use crate::http::client::{Client, UpstreamExt};
+use crate::settings::{self, Timeouts};
+use bytes::Bytes;
+use http::{Method, Request, header};
+use std::time::Duration;
+
+/// max. size of the webhook's response-body to read, it's discarded anyway
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// POST samples to a webhook
+pub struct Webhook {
+    url: String,
+    client: Client,
+    /// publishing is serial, so a hanging webhook must not stall publishing forever
+    timeouts: Timeouts,
+}
+
+impl Webhook {
+    pub fn new(url: String, timeout: Duration) -> Self {
+        Self {
+            url,
+            client: client::new(Some(timeout), &settings::Tls::default())
+                .expect("default tls-settings must be valid"),
+            timeouts: Timeouts {
+                connect: Some(timeout),
+                response: Some(timeout),
+                body: Some(timeout),
+            },
+        }
+    }
+}
+
+impl Sink for Webhook {
+    fn publish<'a>(
+        &'a self,
+        key: &'a str,
+        _sample: &'a Sample,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut request = Request::new(Bytes::copy_from_slice(payload.as_bytes()));
+            *request.method_mut() = Method::POST;
+            request.headers_mut().insert(
+                header::CONTENT_TYPE,
+                http::HeaderValue::from_static("application/json"),
+            );
+            if let Ok(key) = http::HeaderValue::try_from(key) {
+                request.headers_mut().insert("X-Miffy-Key", key);
+            }
+
+            let response = self
+                .client
+                .upstream(request, &self.url, &self.timeouts, Some(MAX_RESPONSE_SIZE))
+                .await?;
+
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(Error::WebhookStatus(response.status()))
+            }
+        })
+    }
+}
@@ -1,50 +1,161 @@
 use super::util::header_ext::TxHeader;
+use crate::diff::comparison::Comparison;
 use crate::http::error;
+use crate::util::json_path::JsonPath;
 use crate::util::serialization;
 use bytes::Bytes;
 use serde::Serialize;
 use serde_json::Value;
 use serde_with::base64::Base64;
 use serde_with::serde_as;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+/// version of the format of published samples, increased on incompatible changes
+pub const SCHEMA_VERSION: &str = "1";
 
 /// a simplified representation of technical errors that may be cloned, serialized etc.
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, PartialEq, Clone, strum::IntoStaticStr)]
 #[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum Error {
     Uri,
     Request,
     Body,
+    Timeout,
 }
 
 impl From<&error::Upstream> for Error {
     fn from(value: &error::Upstream) -> Self {
         match value {
             error::Upstream::InvalidUri(_) => Error::Uri,
+            // connect-timeouts are reported by the client as request-errors
+            error::Upstream::Request(_) if value.is_timeout() => Error::Timeout,
             error::Upstream::Request(_) => Error::Request,
-            error::Upstream::ReadBody(_) => Error::Body,
+            error::Upstream::ReadBody(_) | error::Upstream::BodyTooLarge(_) => Error::Body,
+            error::Upstream::Timeout(_) => Error::Timeout,
         }
     }
 }
 
+/// the kind of difference between reference and candidate
+#[derive(Debug, Serialize, PartialEq, Clone, Copy, strum::IntoStaticStr)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Difference {
+    /// at least one of reference or candidate failed
+    Error,
+    Status,
+    Headers,
+    Body,
+    /// the candidate exceeded the latency-budget
+    Latency,
+}
+
+/// timing of a request to an upstream
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+pub struct Latency {
+    /// time to first byte, i.e. until the response-head was received. None if no response was received
+    #[serde(
+        rename = "ttfb_ms",
+        serialize_with = "serialization::optional_millis",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttfb: Option<Duration>,
+    /// total duration, including reading the response-body
+    #[serde(rename = "total_ms", serialize_with = "serialization::millis")]
+    pub total: Duration,
+}
+
+/// a value that differs between reference and candidate
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Delta<T> {
+    pub reference: T,
+    pub candidate: T,
+}
+
+/// structured differences between reference and candidate (after applying the comparison-rules)
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct Diff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Delta<u16>>,
+
+    /// values of differing headers, by header-name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, Delta<Vec<String>>>,
+
+    /// RFC 6902 JSON Patch transforming the reference-body into the candidate-body. Only available if both are JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<json_patch::Patch>,
+}
+
+/// result of comparing two bodies
+enum BodyDiff {
+    Equal,
+    /// both bodies are JSON, the patch describes the difference
+    Patch(json_patch::Patch),
+    /// bodies differ, but there's no structured description
+    Different,
+}
+
 /// sample represents a shadow-tested request, i.e. a mirrored request that may be analyzed further
 #[derive(Serialize)]
 pub struct Sample {
+    /// when the sample was taken
+    #[serde(serialize_with = "serialization::rfc3339")]
+    pub timestamp: SystemTime,
     pub request: Request,
     pub reference: RequestResult,
     pub candidate: RequestResult,
+    /// name of the candidate, if configured as named candidate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_name: Option<String>,
+    /// number of mirrored requests this sample stands for, i.e. the inverse of the rate it was published at
+    pub weight: f64,
+    pub differences: Vec<Difference>,
+    pub diff: Diff,
+    /// paths that differ between reference and secondary reference, and are thus ignored
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub noise: Vec<JsonPath>,
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, PartialEq, Clone)]
 pub struct RequestResult {
     pub url: String,
+    /// headers of the request as sent to the upstream, if captured
+    #[serde(
+        with = "http_serde::option::header_map",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub request_headers: Option<http::HeaderMap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Latency>,
     #[serde(serialize_with = "serialization::custom_result")]
     pub response: Result<Response, Error>,
 }
 
 impl RequestResult {
     pub fn new(url: String, response: Result<Response, Error>) -> Self {
-        Self { url, response }
+        Self {
+            url,
+            request_headers: None,
+            latency: None,
+            response,
+        }
+    }
+
+    pub fn with_latency(self, latency: Latency) -> Self {
+        Self {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    pub fn with_request_headers(self, request_headers: Option<http::HeaderMap>) -> Self {
+        Self {
+            request_headers,
+            ..self
+        }
     }
 }
 
@@ -53,26 +164,144 @@ impl Sample {
         request: Request,
         reference: RequestResult,
         candidate: RequestResult,
+        candidate_name: Option<String>,
+        comparison: &Comparison,
+        noise: Vec<JsonPath>,
     ) -> Self {
+        let (differences, diff) = Self::compare(&reference, &candidate, comparison, &noise);
+
         Self {
+            timestamp: SystemTime::now(),
             request,
             reference,
             candidate,
+            candidate_name,
+            weight: 1.0,
+            differences,
+            diff,
+            noise,
         }
     }
 
-    pub fn is_equal(&self) -> bool {
-        match (&self.reference.response, &self.candidate.response) {
-            // TODO: maybe compare a relevant subset of headers, e.g. "Location"
-            (Ok(a), Ok(b)) => a.status == b.status && a.body == b.body,
+    fn compare(
+        reference: &RequestResult,
+        candidate: &RequestResult,
+        comparison: &Comparison,
+        noise: &[JsonPath],
+    ) -> (Vec<Difference>, Diff) {
+        let (Ok(a), Ok(b)) = (&reference.response, &candidate.response) else {
             // if any of these fail, they are obviously different. If both fail that's strange, and we're going to report this
-            _ => false,
+            return (vec![Difference::Error], Diff::default());
+        };
+
+        let mut differences = vec![];
+        let mut diff = Diff::default();
+
+        if a.status != b.status {
+            differences.push(Difference::Status);
+            diff.status = Some(Delta {
+                reference: a.status.as_u16(),
+                candidate: b.status.as_u16(),
+            });
+        }
+
+        diff.headers = Response::header_deltas(a, b, comparison);
+        if !diff.headers.is_empty() {
+            differences.push(Difference::Headers);
+        }
+
+        match a.body.diff(&b.body, comparison.ignore.iter().chain(noise)) {
+            BodyDiff::Equal => {}
+            BodyDiff::Patch(patch) => {
+                differences.push(Difference::Body);
+                diff.body = Some(patch);
+            }
+            BodyDiff::Different => differences.push(Difference::Body),
+        }
+
+        if let (Some(a), Some(b)) = (&reference.latency, &candidate.latency) {
+            if comparison.exceeds_latency_budget(a.total, b.total) {
+                differences.push(Difference::Latency);
+            }
+        }
+
+        (differences, diff)
+    }
+
+    /// no differences, i.e. equal responses
+    pub fn is_equal(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub fn with_weight(self, weight: f64) -> Self {
+        Self { weight, ..self }
+    }
+
+    /// metadata to filter and route samples without parsing them, e.g. as kafka-headers
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        let status = |result: &RequestResult| match &result.response {
+            Ok(response) => response.status.as_str().to_string(),
+            Err(e) => <&'static str>::from(e).to_string(),
+        };
+        let differences: Vec<&'static str> = self.differences.iter().map(Into::into).collect();
+
+        let mut metadata = vec![
+            ("route", self.request.route.clone()),
+            ("method", self.request.method.to_string()),
+            ("reference_status", status(&self.reference)),
+            ("candidate_status", status(&self.candidate)),
+            ("differences", differences.join(",")),
+            ("weight", self.weight.to_string()),
+        ];
+        if let Some(name) = &self.candidate_name {
+            metadata.push(("candidate", name.clone()));
         }
+        metadata
     }
+
+    /// the record of this sample as published
+    pub fn to_record(&self) -> Record<'_> {
+        Record {
+            equal: self.is_equal(),
+            sample: self,
+        }
+    }
+
+    /// a compact record of this sample, without request- and response-data
+    pub fn to_match(&self) -> Match<'_> {
+        Match {
+            equal: self.is_equal(),
+            weight: self.weight,
+            method: self.request.method.as_str(),
+            route: &self.request.route,
+            candidate_name: self.candidate_name.as_deref(),
+            differences: &self.differences,
+        }
+    }
+}
+
+/// record of a sample, marked whether the responses are equal
+#[derive(Serialize)]
+pub struct Record<'a> {
+    pub equal: bool,
+    #[serde(flatten)]
+    pub sample: &'a Sample,
+}
+
+/// compact record of a sample, e.g. to compute match-rates
+#[derive(Serialize)]
+pub struct Match<'a> {
+    pub equal: bool,
+    pub weight: f64,
+    pub method: &'a str,
+    pub route: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_name: Option<&'a str>,
+    pub differences: &'a [Difference],
 }
 
 #[serde_as]
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Debug, PartialEq, Clone)]
 #[serde(tag = "type", content = "value")]
 #[serde(rename_all = "lowercase")]
 pub enum Body {
@@ -100,9 +329,30 @@ impl Body {
             Self::Bytes(bytes.clone())
         }
     }
+
+    /// compare with another body, ignoring the given paths if both are JSON
+    fn diff<'a>(&self, other: &Body, ignore: impl Iterator<Item = &'a JsonPath>) -> BodyDiff {
+        match (self, other) {
+            (Self::Json(a), Self::Json(b)) => {
+                let (mut a, mut b) = (a.clone(), b.clone());
+                for path in ignore {
+                    path.remove(&mut a);
+                    path.remove(&mut b);
+                }
+                let patch = json_patch::diff(&a, &b);
+                if patch.0.is_empty() {
+                    BodyDiff::Equal
+                } else {
+                    BodyDiff::Patch(patch)
+                }
+            }
+            _ if self == other => BodyDiff::Equal,
+            _ => BodyDiff::Different,
+        }
+    }
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Debug, PartialEq, Clone)]
 pub struct Response {
     #[serde(with = "http_serde::status_code")]
     status: http::StatusCode,
@@ -111,7 +361,79 @@ pub struct Response {
     body: Body,
 }
 
-#[derive(Serialize)]
+impl Response {
+    pub fn body_mut(&mut self) -> &mut Body {
+        &mut self.body
+    }
+
+    /// detect noise, i.e. paths of JSON bodies that differ between two responses of the reference.
+    ///
+    /// Differing elements of arrays make the whole array noise, since removing single elements would shift the others.
+    /// Differences at the root are not noise, ignoring the root would hide all differences
+    pub fn noise(&self, secondary: &Response) -> Vec<JsonPath> {
+        let (Body::Json(a), Body::Json(b)) = (&self.body, &secondary.body) else {
+            return vec![];
+        };
+        let is_array = |pointer: &str| {
+            [a, b]
+                .iter()
+                .any(|value| value.pointer(pointer).is_some_and(Value::is_array))
+        };
+
+        let mut paths: Vec<String> = json_patch::diff(a, b)
+            .0
+            .iter()
+            .map(|op| {
+                let mut path = op.path().to_string();
+                while let Some((parent, _)) = path.rsplit_once('/') {
+                    if !is_array(parent) {
+                        break;
+                    }
+                    path = parent.to_string();
+                }
+                path
+            })
+            .filter(|path| !path.is_empty())
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        paths
+            .into_iter()
+            .filter_map(|path| JsonPath::try_from(path).ok())
+            .collect()
+    }
+
+    /// compare the headers selected by the comparison-rules, return the values of all differing headers
+    fn header_deltas(
+        a: &Response,
+        b: &Response,
+        comparison: &Comparison,
+    ) -> BTreeMap<String, Delta<Vec<String>>> {
+        let values = |headers: &http::HeaderMap, name| {
+            headers
+                .get_all(name)
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string())
+                .collect::<Vec<_>>()
+        };
+
+        a.headers
+            .keys()
+            .chain(b.headers.keys())
+            .filter(|name| comparison.compares_header(name))
+            .filter_map(|name| {
+                let delta = Delta {
+                    reference: values(&a.headers, name),
+                    candidate: values(&b.headers, name),
+                };
+                (delta.reference != delta.candidate).then(|| (name.to_string(), delta))
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Clone)]
 pub struct Request {
     #[serde(with = "http_serde::method")]
     pub method: http::Method,
@@ -119,6 +441,12 @@ pub struct Request {
     pub uri: http::Uri,
     pub route: String,
     pub params: HashMap<String, String>,
+    /// (redacted) headers of the original request, if captured
+    #[serde(
+        with = "http_serde::header_map",
+        skip_serializing_if = "http::HeaderMap::is_empty"
+    )]
+    pub headers: http::HeaderMap,
     pub body: Body,
 }
 
@@ -127,6 +455,7 @@ impl Request {
         request: &http::Request<Bytes>,
         route: String,
         route_params: Vec<(String, String)>,
+        headers: http::HeaderMap,
     ) -> Self {
         let body = Body::new(request.headers(), request.body());
 
@@ -135,6 +464,7 @@ impl Request {
             uri: request.uri().clone(),
             route,
             params: route_params.into_iter().collect(),
+            headers,
             body,
         }
     }
@@ -155,9 +485,12 @@ impl From<http::Response<Bytes>> for Response {
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod test {
-    use super::{Body, Request, RequestResult, Response};
+    use super::{Body, Delta, Difference, Latency, Request, RequestResult, Response, Sample};
+    use crate::diff::comparison::Comparison;
+    use crate::settings::{HeaderComparison, LatencyBudget};
     use bytes::Bytes;
     use http::{HeaderMap, HeaderValue};
+    use std::time::Duration;
 
     #[test]
     fn serialize_body_none() {
@@ -188,6 +521,24 @@ mod test {
         assert_eq!(actual, r#"{"type":"bytes","value":"AQID"}"#);
     }
 
+    fn sample(reference: Response, candidate: Response, comparison: &Comparison) -> Sample {
+        Sample::new(
+            Request {
+                method: http::Method::GET,
+                uri: "http://localhost".parse().unwrap(),
+                route: "path".to_string(),
+                params: Default::default(),
+                headers: HeaderMap::new(),
+                body: Body::None,
+            },
+            RequestResult::new("http://localhost:3000".to_string(), Ok(reference)),
+            RequestResult::new("http://localhost:3001".to_string(), Ok(candidate)),
+            None,
+            comparison,
+            vec![],
+        )
+    }
+
     #[test]
     fn test_do_not_compare_headers() {
         let mut headers_a = HeaderMap::new();
@@ -199,63 +550,304 @@ mod test {
             HeaderValue::from_static("now plus a little bit later"),
         );
 
-        let sample = super::Sample::new(
+        let sample = sample(
+            Response {
+                status: http::StatusCode::OK,
+                headers: headers_a,
+                body: Body::Json(serde_json::json!({"c:": "d", "a": "b"})),
+            },
+            Response {
+                status: http::StatusCode::OK,
+                headers: headers_b,
+                body: Body::Json(serde_json::json!({"a": "b", "c:": "d"})),
+            },
+            &Comparison::default(),
+        );
+
+        assert!(sample.is_equal());
+    }
+
+    #[test]
+    fn test_do_compare_status_code() {
+        let sample = sample(
+            Response {
+                status: http::StatusCode::OK,
+                headers: Default::default(),
+                body: Body::Json(serde_json::json!({"c:": "d", "a": "b"})),
+            },
+            Response {
+                status: http::StatusCode::BAD_REQUEST,
+                headers: Default::default(),
+                body: Body::Json(serde_json::json!({"a": "b", "c:": "d"})),
+            },
+            &Comparison::default(),
+        );
+
+        assert!(!sample.is_equal());
+        assert_eq!(sample.differences, vec![Difference::Status]);
+        assert_eq!(
+            sample.diff.status,
+            Some(Delta {
+                reference: 200,
+                candidate: 400
+            })
+        );
+    }
+
+    #[test]
+    fn test_compare_listed_headers() {
+        let mut headers_a = HeaderMap::new();
+        headers_a.append("date", HeaderValue::from_static("now"));
+        headers_a.append("location", HeaderValue::from_static("/a"));
+
+        let mut headers_b = HeaderMap::new();
+        headers_b.append("date", HeaderValue::from_static("later"));
+        headers_b.append("location", HeaderValue::from_static("/b"));
+
+        let comparison = Comparison {
+            headers: HeaderComparison {
+                compare: vec!["*".to_string()],
+                ignore: vec!["date".to_string()],
+            },
+            ..Default::default()
+        };
+
+        let sample = sample(
+            Response {
+                status: http::StatusCode::OK,
+                headers: headers_a,
+                body: Body::None,
+            },
+            Response {
+                status: http::StatusCode::OK,
+                headers: headers_b,
+                body: Body::None,
+            },
+            &comparison,
+        );
+
+        assert_eq!(sample.differences, vec![Difference::Headers]);
+        assert_eq!(
+            serde_json::to_value(&sample.diff).unwrap(),
+            serde_json::json!({"headers": {"location": {"reference": ["/a"], "candidate": ["/b"]}}})
+        );
+    }
+
+    #[test]
+    fn test_ignore_paths() {
+        let reference = || Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::Json(
+                serde_json::json!({"meta": {"generatedAt": "now"}, "id": 1, "a": "b"}),
+            ),
+        };
+        let candidate = || Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::Json(
+                serde_json::json!({"meta": {"generatedAt": "later"}, "id": 2, "a": "b"}),
+            ),
+        };
+
+        let actual = sample(reference(), candidate(), &Comparison::default());
+        assert_eq!(actual.differences, vec![Difference::Body]);
+        assert_eq!(
+            serde_json::to_value(&actual.diff).unwrap(),
+            serde_json::json!({"body": [
+                {"op": "replace", "path": "/id", "value": 2},
+                {"op": "replace", "path": "/meta/generatedAt", "value": "later"},
+            ]})
+        );
+
+        let comparison = Comparison {
+            ignore: vec![
+                "$.meta.generatedAt".try_into().unwrap(),
+                "$..id".try_into().unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert!(sample(reference(), candidate(), &comparison).is_equal());
+    }
+
+    #[test]
+    fn test_ignore_noise() {
+        let response = |generated_at: &str, result: u32| Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::Json(serde_json::json!({"generatedAt": generated_at, "result": result})),
+        };
+
+        let noise = response("now", 1).noise(&response("a bit later", 1));
+        assert_eq!(noise, vec!["/generatedAt".try_into().unwrap()]);
+
+        let sample = |candidate| {
+            Sample::new(
+                Request {
+                    method: http::Method::GET,
+                    uri: "http://localhost".parse().unwrap(),
+                    route: "path".to_string(),
+                    params: Default::default(),
+                    headers: HeaderMap::new(),
+                    body: Body::None,
+                },
+                RequestResult::new("http://localhost:3000".to_string(), Ok(response("now", 1))),
+                RequestResult::new("http://localhost:3001".to_string(), Ok(candidate)),
+                None,
+                &Comparison::default(),
+                noise.clone(),
+            )
+        };
+
+        assert!(sample(response("later", 1)).is_equal());
+
+        let different = sample(response("later", 2));
+        assert_eq!(
+            serde_json::to_value(&different.diff).unwrap(),
+            serde_json::json!({"body": [{"op": "replace", "path": "/result", "value": 2}]})
+        );
+        assert_eq!(different.differences, vec![Difference::Body]);
+    }
+
+    #[test]
+    fn test_noise_in_arrays() {
+        let response = |value| Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::Json(value),
+        };
+
+        let noise = response(serde_json::json!({"items": [1, 2, 3], "id": 1}))
+            .noise(&response(serde_json::json!({"items": [1, 5, 6], "id": 1})));
+        assert_eq!(noise, vec!["/items".try_into().unwrap()]);
+
+        let sample = Sample::new(
             Request {
                 method: http::Method::GET,
                 uri: "http://localhost".parse().unwrap(),
                 route: "path".to_string(),
                 params: Default::default(),
+                headers: HeaderMap::new(),
                 body: Body::None,
             },
             RequestResult::new(
                 "http://localhost:3000".to_string(),
-                Ok(Response {
-                    status: http::StatusCode::OK,
-                    headers: headers_a,
-                    body: Body::Json(serde_json::json!({"c:": "d", "a": "b"})),
-                }),
+                Ok(response(serde_json::json!({"items": [1, 2, 3], "id": 1}))),
             ),
             RequestResult::new(
                 "http://localhost:3001".to_string(),
-                Ok(Response {
-                    status: http::StatusCode::OK,
-                    headers: headers_b,
-                    body: Body::Json(serde_json::json!({"a": "b", "c:": "d"})),
-                }),
+                Ok(response(serde_json::json!({"items": [1, 7, 8], "id": 2}))),
             ),
+            None,
+            &Comparison::default(),
+            noise,
         );
+        assert_eq!(
+            serde_json::to_value(&sample.diff).unwrap(),
+            serde_json::json!({"body": [{"op": "replace", "path": "/id", "value": 2}]})
+        );
+    }
 
-        assert!(sample.is_equal());
+    #[test]
+    fn test_noise_at_root() {
+        let response = |value| Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::Json(value),
+        };
+
+        let noise = response(serde_json::json!([1])).noise(&response(serde_json::json!({"a": 1})));
+        assert!(noise.is_empty());
+
+        // elements of a root-array can't be ignored either, without ignoring the whole body
+        let noise = response(serde_json::json!([1, 2])).noise(&response(serde_json::json!([1, 3])));
+        assert!(noise.is_empty());
     }
 
     #[test]
-    fn test_do_compare_status_code() {
-        let sample = super::Sample::new(
+    fn test_metadata() {
+        let response = |status| Response {
+            status,
+            headers: Default::default(),
+            body: Body::None,
+        };
+        let sample = Sample::new(
             Request {
                 method: http::Method::GET,
                 uri: "http://localhost".parse().unwrap(),
                 route: "path".to_string(),
                 params: Default::default(),
+                headers: HeaderMap::new(),
                 body: Body::None,
             },
             RequestResult::new(
                 "http://localhost:3000".to_string(),
-                Ok(Response {
-                    status: http::StatusCode::OK,
-                    headers: Default::default(),
-                    body: Body::Json(serde_json::json!({"c:": "d", "a": "b"})),
-                }),
+                Ok(response(http::StatusCode::OK)),
             ),
             RequestResult::new(
-                "http://localhost:3000".to_string(),
-                Ok(Response {
-                    status: http::StatusCode::BAD_REQUEST,
-                    headers: Default::default(),
-                    body: Body::Json(serde_json::json!({"a": "b", "c:": "d"})),
-                }),
+                "http://localhost:3001".to_string(),
+                Ok(response(http::StatusCode::NOT_FOUND)),
             ),
+            Some("rewrite".to_string()),
+            &Comparison::default(),
+            vec![],
         );
 
-        assert!(!sample.is_equal());
+        assert_eq!(
+            sample.metadata(),
+            vec![
+                ("route", "path".to_string()),
+                ("method", "GET".to_string()),
+                ("reference_status", "200".to_string()),
+                ("candidate_status", "404".to_string()),
+                ("differences", "status".to_string()),
+                ("weight", "1".to_string()),
+                ("candidate", "rewrite".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latency_budget() {
+        let response = || Response {
+            status: http::StatusCode::OK,
+            headers: Default::default(),
+            body: Body::None,
+        };
+        let latency = |ttfb, total| Latency {
+            ttfb: Some(Duration::from_millis(ttfb)),
+            total: Duration::from_millis(total),
+        };
+        let comparison = Comparison {
+            latency_budget: LatencyBudget {
+                absolute: Some(Duration::from_millis(50)),
+                relative: None,
+            },
+            ..Default::default()
+        };
+
+        let sample = Sample::new(
+            Request {
+                method: http::Method::GET,
+                uri: "http://localhost".parse().unwrap(),
+                route: "path".to_string(),
+                params: Default::default(),
+                headers: HeaderMap::new(),
+                body: Body::None,
+            },
+            RequestResult::new("http://localhost:3000".to_string(), Ok(response()))
+                .with_latency(latency(10, 20)),
+            RequestResult::new("http://localhost:3001".to_string(), Ok(response()))
+                .with_latency(latency(80, 90)),
+            None,
+            &comparison,
+            vec![],
+        );
+
+        assert_eq!(sample.differences, vec![Difference::Latency]);
+        assert_eq!(
+            serde_json::to_value(sample.candidate.latency).unwrap(),
+            serde_json::json!({"ttfb_ms": 80.0, "total_ms": 90.0})
+        );
     }
 }
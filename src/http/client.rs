@@ -1,32 +1,579 @@
-use crate::http::slurp;
+use crate::http::error::{Tls, Upstream};
+use crate::http::slurp::{self, Slurped};
+use crate::http::tls::{read_certs, read_key};
+use crate::http::{Body, full, headers};
+use crate::settings::{self, Timeouts};
 use bytes::Bytes;
-use http::{Request, Response, Uri};
-use http_body_util::Full;
+use http::header::HOST;
+use http::uri::{Authority, Scheme};
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode, Uri, Version};
+use hyper::body::Incoming;
+use hyper_rustls::{
+    DefaultServerNameResolver, HttpsConnector, HttpsConnectorBuilder, ResolveServerName,
+};
 use hyper_util::client::legacy::connect::HttpConnector;
+use rustls::RootCertStore;
+use rustls::crypto::ring;
+use rustls_pki_types::ServerName;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-pub type Client = hyper_util::client::legacy::Client<HttpConnector, Full<Bytes>>;
+type Inner = hyper_util::client::legacy::Client<HttpsConnector<HttpConnector>, Body>;
+
+/// client for requests to an upstream, via http or https
+#[derive(Clone)]
+pub struct Client {
+    /// the client with the default connect-timeout
+    inner: Inner,
+    connect_timeout: Option<Duration>,
+    /// clients with other connect-timeouts (e.g. of single routes), built on first use since each has its own
+    /// connection-pool
+    by_connect_timeout: Arc<Mutex<HashMap<Duration, Inner>>>,
+    tls_config: Arc<rustls::ClientConfig>,
+    /// server-names and Host-headers to use instead of the URL's host and the original Host-header
+    names: Arc<Upstreams>,
+}
+
+impl Client {
+    /// the client connecting with the given timeout
+    fn inner(&self, connect_timeout: Option<Duration>) -> Inner {
+        match connect_timeout {
+            Some(timeout) if connect_timeout != self.connect_timeout => self
+                .by_connect_timeout
+                .lock()
+                .expect("clients-lock poisoned")
+                .entry(timeout)
+                .or_insert_with(|| build(Some(timeout), &self.tls_config, &self.names))
+                .clone(),
+            _ => self.inner.clone(),
+        }
+    }
+
+    /// the headers as sent upstream (for requests not upgrading the connection): without hop-by-hop headers, with
+    /// the overridden Host-header
+    pub fn sent_headers(&self, headers: &HeaderMap, uri: &str) -> HeaderMap {
+        let mut headers = headers.clone();
+        headers::strip_hop_by_hop(&mut headers);
+        if let Some(host) = Uri::try_from(uri)
+            .ok()
+            .and_then(|uri| self.names.get(&uri).host.as_ref())
+        {
+            headers.insert(HOST, host.clone());
+        }
+        headers
+    }
+}
+
+/// server-name and Host-header to use instead of the URL's host and the original Host-header
+struct Names {
+    server_name: Option<ServerName<'static>>,
+    host: Option<HeaderValue>,
+}
+
+impl Names {
+    fn new(server_name: Option<&String>, host: Option<&String>) -> Result<Self, Tls> {
+        let server_name = server_name
+            .map(|name| {
+                ServerName::try_from(name.clone()).map_err(|_| Tls::ServerName(name.clone()))
+            })
+            .transpose()?;
+        let host = host
+            .map(|host| HeaderValue::try_from(host).map_err(|_| Tls::Host(host.clone())))
+            .transpose()?;
+        Ok(Self { server_name, host })
+    }
+}
+
+/// the names of all upstreams of a role, and of single upstreams overriding them
+struct Upstreams {
+    default: Names,
+    /// by scheme and authority of the upstream's URL
+    upstreams: Vec<(Option<Scheme>, Option<Authority>, Names)>,
+}
+
+impl Upstreams {
+    fn new(tls: &settings::Tls) -> Result<Self, Tls> {
+        let upstreams = tls
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                let uri =
+                    Uri::try_from(&upstream.url).map_err(|_| Tls::Url(upstream.url.clone()))?;
+                let names = Names::new(
+                    upstream.server_name.as_ref().or(tls.server_name.as_ref()),
+                    upstream.host.as_ref().or(tls.host.as_ref()),
+                )?;
+                Ok((uri.scheme().cloned(), uri.authority().cloned(), names))
+            })
+            .collect::<Result<_, Tls>>()?;
+
+        Ok(Self {
+            default: Names::new(tls.server_name.as_ref(), tls.host.as_ref())?,
+            upstreams,
+        })
+    }
+
+    /// the names to use for the given URI
+    fn get(&self, uri: &Uri) -> &Names {
+        self.upstreams
+            .iter()
+            .find(|(scheme, authority, _)| {
+                uri.scheme() == scheme.as_ref() && uri.authority() == authority.as_ref()
+            })
+            .map_or(&self.default, |(_, _, names)| names)
+    }
+}
+
+/// resolves the configured server-name of an upstream, or the URL's host
+struct ServerNames(Arc<Upstreams>);
+
+impl ResolveServerName for ServerNames {
+    fn resolve(
+        &self,
+        uri: &Uri,
+    ) -> Result<ServerName<'static>, Box<dyn std::error::Error + Sync + Send>> {
+        match &self.0.get(uri).server_name {
+            Some(name) => Ok(name.clone()),
+            None => DefaultServerNameResolver::default().resolve(uri),
+        }
+    }
+}
+
+/// build the rustls-config from the given settings
+fn tls_config(tls: &settings::Tls) -> Result<rustls::ClientConfig, Tls> {
+    let roots = match &tls.ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(ca)? {
+                roots.add(cert)?;
+            }
+            roots
+        }
+        None => RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        },
+    };
+
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+
+    let config = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => {
+            builder.with_client_auth_cert(read_certs(cert)?, read_key(key)?)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(Tls::ClientAuth),
+    };
+
+    Ok(config)
+}
+
+/// build a new client
+pub fn new(connect_timeout: Option<Duration>, tls: &settings::Tls) -> Result<Client, Tls> {
+    let tls_config = Arc::new(tls_config(tls)?);
+    let names = Arc::new(Upstreams::new(tls)?);
+
+    Ok(Client {
+        inner: build(connect_timeout, &tls_config, &names),
+        connect_timeout,
+        by_connect_timeout: Arc::default(),
+        tls_config,
+        names,
+    })
+}
+
+fn build(
+    connect_timeout: Option<Duration>,
+    tls_config: &rustls::ClientConfig,
+    names: &Arc<Upstreams>,
+) -> Inner {
+    let mut connector = HttpConnector::new();
+    connector.set_connect_timeout(connect_timeout);
+    connector.enforce_http(false);
+
+    let connector = HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config.clone())
+        .https_or_http()
+        .with_server_name_resolver(ServerNames(names.clone()))
+        .enable_http1()
+        .wrap_connector(connector);
+
+    hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build(connector)
+}
 
 pub trait UpstreamExt {
+    /// send the request, respond as soon as the response-head is received, i.e. stream the response-body
+    async fn send(
+        &self,
+        req: Request<Body>,
+        uri: &str,
+        timeouts: &Timeouts,
+    ) -> Result<Response<Incoming>, Upstream>;
+
+    /// send the request and read the whole response-body, failing if it's larger than `max_body_size` bytes
     async fn upstream(
         &self,
-        req: http::Request<Bytes>,
+        req: Request<Bytes>,
         uri: &str,
-    ) -> Result<Response<Bytes>, crate::http::error::Upstream>;
+        timeouts: &Timeouts,
+        max_body_size: Option<usize>,
+    ) -> Result<Response<Bytes>, Upstream>;
+}
+
+/// await the future, fail with a timeout-error if it does not complete in time
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    phase: &'static str,
+    future: impl Future<Output = Result<T, Upstream>>,
+) -> Result<T, Upstream> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| Upstream::Timeout(phase))?,
+        None => future.await,
+    }
+}
+
+/// read the response-body, up to `limit` bytes
+pub async fn read_body(
+    response: Response<Incoming>,
+    timeouts: &Timeouts,
+    limit: usize,
+) -> Result<Response<Slurped<Incoming>>, Upstream> {
+    let (head, body) = response.into_parts();
+    let body = with_timeout(timeouts.body, "response-body", async {
+        slurp::body(body, limit).await.map_err(Upstream::ReadBody)
+    })
+    .await?;
+
+    Ok(Response::from_parts(head, body))
+}
+
+/// read the whole response-body, failing if it's larger than `max_body_size` bytes
+pub async fn read_complete_body(
+    response: Response<Incoming>,
+    timeouts: &Timeouts,
+    max_body_size: Option<usize>,
+) -> Result<Response<Bytes>, Upstream> {
+    let limit = max_body_size.unwrap_or(usize::MAX);
+
+    let (head, body) = read_body(response, timeouts, limit).await?.into_parts();
+    match body {
+        Slurped::Complete(body) => Ok(Response::from_parts(head, body)),
+        Slurped::TooLarge(_) => Err(Upstream::BodyTooLarge(limit)),
+    }
 }
 
 impl UpstreamExt for Client {
-    async fn upstream(
+    async fn send(
         &self,
-        mut req: Request<Bytes>,
+        mut req: Request<Body>,
         uri: &str,
-    ) -> Result<Response<Bytes>, crate::http::error::Upstream> {
+        timeouts: &Timeouts,
+    ) -> Result<Response<Incoming>, Upstream> {
         *req.uri_mut() = Uri::try_from(uri)?;
+        let host = self.names.get(req.uri()).host.clone();
+        // the incoming request may be HTTP/2, but upstreams are always requested via HTTP/1.1
+        *req.version_mut() = Version::HTTP_11;
+        headers::strip_request(&mut req);
+        if let Some(host) = host {
+            req.headers_mut().insert(HOST, host);
+        }
+
+        let mut response = with_timeout(timeouts.response, "response", async {
+            let client = self.inner(timeouts.connect);
+            client.request(req).await.map_err(Upstream::Request)
+        })
+        .await?;
+
+        // keep the headers of upgrades (e.g. to WebSockets), since they are passed through
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            headers::strip_hop_by_hop(response.headers_mut());
+        }
+
+        Ok(response)
+    }
+
+    async fn upstream(
+        &self,
+        req: Request<Bytes>,
+        uri: &str,
+        timeouts: &Timeouts,
+        max_body_size: Option<usize>,
+    ) -> Result<Response<Bytes>, Upstream> {
+        let response = self.send(req.map(full), uri, timeouts).await?;
+        read_complete_body(response, timeouts, max_body_size).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::UpstreamExt;
+    use crate::domain;
+    use crate::http::error::{self, Upstream};
+    use crate::settings::{Timeouts, Tls, UpstreamNames};
+    use crate::util::temp_dir::TempDir;
+    use bytes::Bytes;
+    use http::header::HOST;
+    use http_body_util::Full;
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::RootCertStore;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls_pki_types::PrivateKeyDer;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_response_timeout() {
+        // accept connections, but never respond
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let timeouts = Timeouts {
+            response: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
 
-        let response = self
-            .request(req.map(Full::new))
+        let actual = super::new(None, &Tls::default())
+            .unwrap()
+            .upstream(
+                http::Request::new(Bytes::new()),
+                &format!("http://{addr}/"),
+                &timeouts,
+                None,
+            )
             .await
-            .map_err(crate::http::error::Upstream::Request)?;
+            .unwrap_err();
+
+        assert!(matches!(actual, Upstream::Timeout("response")));
+        assert_eq!(domain::Error::from(&actual), domain::Error::Timeout);
+    }
+
+    #[test]
+    fn test_clients_by_connect_timeout() {
+        let client = super::new(Some(Duration::from_secs(1)), &Tls::default()).unwrap();
+
+        client.inner(None);
+        client.inner(Some(Duration::from_secs(1)));
+        assert!(client.by_connect_timeout.lock().unwrap().is_empty());
+
+        client.inner(Some(Duration::from_millis(50)));
+        client.clone().inner(Some(Duration::from_millis(50)));
+        client.inner(Some(Duration::from_secs(2)));
+        let by_connect_timeout = client.by_connect_timeout.lock().unwrap();
+        assert_eq!(
+            by_connect_timeout
+                .keys()
+                .copied()
+                .collect::<std::collections::BTreeSet<_>>(),
+            [Duration::from_millis(50), Duration::from_secs(2)].into()
+        );
+    }
+
+    /// PEM-files of a CA and a client-certificate issued by it, in a temporary directory
+    struct Pki {
+        dir: TempDir,
+        ca: rcgen::CertifiedIssuer<'static, KeyPair>,
+    }
+
+    impl Pki {
+        fn new(name: &str) -> Self {
+            let dir = TempDir::new(name);
+
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca =
+                rcgen::CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+            std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec!["client".to_string()])
+                .unwrap()
+                .signed_by(&key, &ca)
+                .unwrap();
+            std::fs::write(dir.join("client.pem"), cert.pem()).unwrap();
+            std::fs::write(dir.join("client.key"), key.serialize_pem()).unwrap();
+
+            Self { dir, ca }
+        }
+
+        /// https-server for `localhost`, requiring client-certificates issued by the CA, responding with the Host-header
+        async fn serve(&self) -> SocketAddr {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec!["localhost".to_string()])
+                .unwrap()
+                .signed_by(&key, &self.ca)
+                .unwrap();
+
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca.der().clone()).unwrap();
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .unwrap();
+            let config = rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(
+                    vec![cert.der().clone()],
+                    PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+                )
+                .unwrap();
+            let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let Ok(stream) = acceptor.accept(stream).await else {
+                        continue;
+                    };
+                    let service = service_fn(|req: http::Request<Incoming>| async move {
+                        let host = req.headers().get(HOST).cloned().unwrap();
+                        Ok::<_, Infallible>(http::Response::new(Full::new(Bytes::copy_from_slice(
+                            host.as_bytes(),
+                        ))))
+                    });
+                    tokio::spawn(
+                        http1::Builder::new().serve_connection(TokioIo::new(stream), service),
+                    );
+                }
+            });
+            addr
+        }
+    }
+
+    #[tokio::test]
+    async fn test_https_with_client_certificate() {
+        let pki = Pki::new("tls");
+        let addr = pki.serve().await;
+
+        let tls = Tls {
+            ca: Some(pki.dir.join("ca.pem")),
+            cert: Some(pki.dir.join("client.pem")),
+            key: Some(pki.dir.join("client.key")),
+            server_name: Some("localhost".to_string()),
+            host: Some("example.com".to_string()),
+            upstreams: vec![],
+        };
+
+        // incoming HTTP/2-requests are sent to upstreams via HTTP/1.1
+        let request = http::Request::builder()
+            .version(http::Version::HTTP_2)
+            .body(Bytes::new())
+            .unwrap();
+        let response = super::new(None, &tls)
+            .unwrap()
+            .upstream(
+                request,
+                &format!("https://{addr}/"),
+                &Timeouts::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "example.com");
+
+        // the server requires a client-certificate
+        let without_client_auth = Tls {
+            cert: None,
+            key: None,
+            ..tls
+        };
+        let actual = super::new(None, &without_client_auth)
+            .unwrap()
+            .upstream(
+                http::Request::new(Bytes::new()),
+                &format!("https://{addr}/"),
+                &Timeouts::default(),
+                None,
+            )
+            .await;
+        assert!(actual.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_names_per_upstream() {
+        let pki = Pki::new("upstreams");
+        let addr = pki.serve().await;
+        let other = pki.serve().await;
+
+        // the certificate is issued for `localhost`, not for `127.0.0.1`
+        let tls = Tls {
+            ca: Some(pki.dir.join("ca.pem")),
+            cert: Some(pki.dir.join("client.pem")),
+            key: Some(pki.dir.join("client.key")),
+            host: Some("example.com".to_string()),
+            upstreams: vec![UpstreamNames {
+                url: format!("https://{addr}"),
+                server_name: Some("localhost".to_string()),
+                host: Some("upstream.example.com".to_string()),
+            }],
+            ..Default::default()
+        };
+        let client = super::new(None, &tls).unwrap();
+        let get = async |url: String| {
+            client
+                .upstream(
+                    http::Request::new(Bytes::new()),
+                    &format!("{url}/path"),
+                    &Timeouts::default(),
+                    None,
+                )
+                .await
+        };
+
+        let response = get(format!("https://{addr}")).await.unwrap();
+        assert_eq!(response.body(), "upstream.example.com");
+        let response = get(format!("https://localhost:{}", other.port()))
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "example.com");
+        assert!(get(format!("https://{other}")).await.is_err());
+        assert_eq!(
+            client.sent_headers(&http::HeaderMap::new(), &format!("https://{addr}/path"))[HOST],
+            "upstream.example.com"
+        );
+    }
+
+    #[test]
+    fn test_invalid_tls() {
+        let pki = Pki::new("invalid-tls");
+
+        let actual = super::new(
+            None,
+            &Tls {
+                cert: Some(pki.dir.join("client.pem")),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(actual, Err(error::Tls::ClientAuth)));
 
-        slurp::response(response).await
+        let actual = super::new(
+            None,
+            &Tls {
+                ca: Some(pki.dir.join("missing.pem")),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(actual, Err(error::Tls::Pem(..))));
     }
 }
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Debug, Error, strum::IntoStaticStr)]
@@ -10,4 +11,54 @@ pub enum Upstream {
 
     #[error(transparent)]
     InvalidUri(#[from] http::uri::InvalidUri),
+
+    #[error("upstream timed out sending the {0}")]
+    Timeout(&'static str),
+
+    #[error("upstream response body exceeds the limit of {0} bytes")]
+    BodyTooLarge(usize),
+}
+
+impl Upstream {
+    /// check if this error is caused by a timeout, including timeouts while connecting
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Upstream::Timeout(_) => true,
+            Upstream::Request(e) => {
+                let mut source = std::error::Error::source(e);
+                while let Some(e) = source {
+                    if e.downcast_ref::<std::io::Error>()
+                        .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+                    {
+                        return true;
+                    }
+                    source = e.source();
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+/// invalid TLS-settings for an upstream
+#[derive(Debug, Error)]
+pub enum Tls {
+    #[error("error reading PEM-file {0}")]
+    Pem(PathBuf, #[source] rustls_pki_types::pem::Error),
+
+    #[error("client-certificate and key must be set together")]
+    ClientAuth,
+
+    #[error("invalid server-name: {0}")]
+    ServerName(String),
+
+    #[error("invalid host: {0}")]
+    Host(String),
+
+    #[error("invalid URL of upstream: {0}")]
+    Url(String),
+
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
 }
@@ -0,0 +1,242 @@
+use crate::http::upgrade;
+use crate::settings::Forwarding;
+use http::header::{CONNECTION, FORWARDED, HOST, TE, TRAILER, TRANSFER_ENCODING, UPGRADE};
+use http::{HeaderMap, HeaderName, HeaderValue, Request};
+use std::net::{IpAddr, SocketAddr};
+
+const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+const PROXY_CONNECTION: HeaderName = HeaderName::from_static("proxy-connection");
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// the client of an incoming request
+#[derive(Debug, Clone, Copy)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// if the connection is TLS-terminated by miffy
+    pub tls: bool,
+}
+
+/// remove hop-by-hop headers (see RFC 9110, section 7.6.1), i.e. headers only meaningful for a single connection:
+/// the `Connection`-header, all headers listed by it, and well-known hop-by-hop headers
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+
+    for name in listed.iter().chain(&[
+        CONNECTION,
+        KEEP_ALIVE,
+        PROXY_CONNECTION,
+        TE,
+        TRAILER,
+        TRANSFER_ENCODING,
+        UPGRADE,
+    ]) {
+        headers.remove(name);
+    }
+}
+
+/// remove hop-by-hop headers of a request to an upstream. Upgrades (e.g. to WebSockets) are kept, since they
+/// are passed through
+pub fn strip_request<B>(req: &mut Request<B>) {
+    let upgrade = upgrade::is_requested(req)
+        .then(|| req.headers().get(UPGRADE).cloned())
+        .flatten();
+
+    strip_hop_by_hop(req.headers_mut());
+
+    if let Some(upgrade) = upgrade {
+        req.headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        req.headers_mut().insert(UPGRADE, upgrade);
+    }
+}
+
+/// append `value` to the (comma-separated) header, or set it if not present yet
+fn append(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let value = match headers.get(&name).and_then(|v| v.to_str().ok()) {
+        Some(existing) => format!("{existing}, {value}"),
+        None => value.to_string(),
+    };
+
+    if let Ok(value) = HeaderValue::try_from(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// quote the value as quoted-string, escaping `"` and `\` as quoted-pairs (RFC 7230, section 3.2.6)
+fn quoted_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// add headers telling upstreams about the client, as configured
+pub fn add_forwarded<B>(req: &mut Request<B>, peer: Peer, forwarding: &Forwarding) {
+    let proto = if peer.tls { "https" } else { "http" };
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().authority().map(http::uri::Authority::as_str))
+        .map(ToString::to_string);
+    let ip = peer.addr.ip().to_canonical();
+    let headers = req.headers_mut();
+
+    if forwarding.x_forwarded {
+        append(headers, X_FORWARDED_FOR, &ip.to_string());
+        // values set by a proxy in front of miffy are more accurate
+        if !headers.contains_key(X_FORWARDED_PROTO) {
+            headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(proto));
+        }
+        if let Some(host) = host.as_ref().and_then(|h| HeaderValue::try_from(h).ok()) {
+            headers.entry(X_FORWARDED_HOST).or_insert(host);
+        }
+    }
+
+    if forwarding.forwarded {
+        // IPv6-addresses must be quoted, see RFC 7239, section 6
+        let node = match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("\"[{ip}]\""),
+        };
+        let mut element = format!("for={node};proto={proto}");
+        if let Some(host) = host {
+            element.push_str(&format!(";host={}", quoted_string(&host)));
+        }
+        append(headers, FORWARDED, &element);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Peer;
+    use crate::settings::Forwarding;
+    use bytes::Bytes;
+    use http::HeaderMap;
+
+    fn request(headers: &[(&str, &str)]) -> http::Request<Bytes> {
+        let mut builder = http::Request::get("/api");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Bytes::new()).unwrap()
+    }
+
+    fn names(headers: &HeaderMap) -> Vec<&str> {
+        headers.keys().map(http::HeaderName::as_str).collect()
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut req = request(&[
+            ("connection", "keep-alive, X-Custom"),
+            ("keep-alive", "timeout=5"),
+            ("x-custom", "hop"),
+            ("te", "trailers"),
+            ("transfer-encoding", "chunked"),
+            ("accept", "application/json"),
+        ]);
+
+        super::strip_request(&mut req);
+
+        assert_eq!(names(req.headers()), vec!["accept"]);
+    }
+
+    #[test]
+    fn test_keep_upgrade() {
+        let mut req = request(&[
+            ("connection", "Upgrade, keep-alive"),
+            ("upgrade", "websocket"),
+            ("keep-alive", "timeout=5"),
+        ]);
+
+        super::strip_request(&mut req);
+
+        assert_eq!(req.headers()["connection"], "upgrade");
+        assert_eq!(req.headers()["upgrade"], "websocket");
+        assert!(!req.headers().contains_key("keep-alive"));
+    }
+
+    #[test]
+    fn test_add_forwarded() {
+        let mut req = request(&[
+            ("host", "example.com"),
+            ("x-forwarded-for", "10.0.0.1"),
+            ("x-forwarded-proto", "https"),
+        ]);
+        let peer = Peer {
+            addr: "[::ffff:10.0.0.2]:1234".parse().unwrap(),
+            tls: false,
+        };
+        let forwarding = Forwarding {
+            x_forwarded: true,
+            forwarded: true,
+        };
+
+        super::add_forwarded(&mut req, peer, &forwarding);
+
+        assert_eq!(req.headers()["x-forwarded-for"], "10.0.0.1, 10.0.0.2");
+        assert_eq!(req.headers()["x-forwarded-proto"], "https");
+        assert_eq!(req.headers()["x-forwarded-host"], "example.com");
+        assert_eq!(
+            req.headers()["forwarded"],
+            r#"for=10.0.0.2;proto=http;host="example.com""#
+        );
+    }
+
+    #[test]
+    fn test_forwarded_host_escaped() {
+        let mut req = request(&[("host", r#"evil.com";for="1.2.3.4\"#)]);
+        let peer = Peer {
+            addr: "10.0.0.2:1234".parse().unwrap(),
+            tls: false,
+        };
+        let forwarding = Forwarding {
+            x_forwarded: false,
+            forwarded: true,
+        };
+
+        super::add_forwarded(&mut req, peer, &forwarding);
+
+        assert_eq!(
+            req.headers()["forwarded"],
+            r#"for=10.0.0.2;proto=http;host="evil.com\";for=\"1.2.3.4\\""#
+        );
+    }
+
+    #[test]
+    fn test_forwarded_ipv6() {
+        let mut req = request(&[]);
+        let peer = Peer {
+            addr: "[2001:db8::1]:1234".parse().unwrap(),
+            tls: true,
+        };
+        let forwarding = Forwarding {
+            x_forwarded: false,
+            forwarded: true,
+        };
+
+        super::add_forwarded(&mut req, peer, &forwarding);
+
+        assert_eq!(
+            req.headers()["forwarded"],
+            r#"for="[2001:db8::1]";proto=https"#
+        );
+        assert!(!req.headers().contains_key("x-forwarded-for"));
+    }
+}
@@ -1,6 +1,21 @@
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+
 pub mod client;
 pub mod error;
+pub mod headers;
 pub mod model;
 pub mod slurp;
+pub mod tls;
+pub mod upgrade;
 
 pub const SHADOW_TEST_HEADER: &str = "X-Shadow-Test-Role";
+
+/// body of requests to upstreams and of responses to clients, either buffered or streamed
+pub type Body = BoxBody<Bytes, hyper::Error>;
+
+/// a body with the given (buffered) content
+pub fn full(bytes: Bytes) -> Body {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
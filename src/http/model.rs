@@ -1,32 +1,71 @@
+use crate::diff::comparison::Comparison;
+use crate::diff::redact::{BodyRedaction, HeaderCapture};
+use crate::diff::rewrite::Rewrite;
 use crate::domain;
+use crate::settings::{Timeouts, UpstreamTimeouts};
 use bytes::Bytes;
-use http::Response;
+use http::{HeaderMap, Response};
+use std::sync::Arc;
+use tokio::sync::OwnedSemaphorePermit;
 use tokio::sync::oneshot::{Receiver, Sender};
 
-/// type of the value sent over the channel
-pub type ChannelValue = (String, Result<Response<Bytes>, domain::Error>);
+/// type of the value sent over the channel: url, headers as sent (if captured), latency and response of the reference
+pub type ChannelValue = (
+    String,
+    Option<HeaderMap>,
+    domain::Latency,
+    Result<Response<Bytes>, domain::Error>,
+);
+
+/// a candidate of an experiment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// name of the candidate, if configured as named candidate
+    pub name: Option<String>,
+    pub uri: String,
+}
+
+/// everything the mirror-task needs to run an experiment
+pub struct Experiment {
+    /// if given in config: custom key
+    pub key: Option<String>,
+    /// path of the route
+    pub route: String,
+    /// parameters as extracted from the route
+    pub route_params: Vec<(String, String)>,
+    /// candidates to send the request to, one sample is published per candidate
+    pub candidates: Vec<Candidate>,
+    /// if configured: how to rewrite the headers of requests to the candidates (path and query are already rewritten)
+    pub rewrite: Option<Arc<Rewrite>>,
+    /// if configured: uri of a second instance of the reference, to detect noise
+    pub secondary_reference_uri: Option<String>,
+    /// which request-headers to include in the sample
+    pub headers: Arc<HeaderCapture>,
+    /// rules how to compare reference and candidate
+    pub comparison: Arc<Comparison>,
+    /// if configured: values to redact from the bodies before comparing and publishing
+    pub redact_body: Option<Arc<BodyRedaction>>,
+    /// timeouts for candidate and secondary reference
+    pub timeouts: UpstreamTimeouts,
+    /// max. size of request- and response-bodies to buffer for the experiment
+    pub max_body_size: usize,
+    /// permits to run a mirror-task, released once the experiment is finished
+    pub permits: Vec<OwnedSemaphorePermit>,
+    pub rx: Receiver<ChannelValue>,
+}
 
 /// a mode for this request.
 ///
 /// Either it's an experiment, or simple proxy
 pub enum RequestMode {
     Proxy,
-    Experiment {
-        /// if given in config: custom key
-        key: Option<String>,
-        /// path of the route
-        route: String,
-        /// parameters as extracted from the route
-        route_params: Vec<(String, String)>,
-        request: http::Request<Bytes>,
-        candidate_uri: String,
-        rx: Receiver<ChannelValue>,
-    },
+    Experiment(Box<Experiment>),
 }
 
 /// context for a request: the (live/reference) upstream uri to use, the mode, and an optional sender to send results to
 pub struct RequestContext {
     pub reference_uri: String,
+    pub reference_timeouts: Timeouts,
     pub tx: Option<Sender<ChannelValue>>,
     pub mode: RequestMode,
 }
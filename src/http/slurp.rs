@@ -1,17 +1,117 @@
 use crate::http::error::Upstream;
 use crate::http::error::Upstream::ReadBody;
+use bytes::BytesMut;
 use http_body_util::BodyExt;
-use hyper::body::Bytes;
-use hyper::{Request, Response};
-
-pub async fn request(req: Request<hyper::body::Incoming>) -> hyper::Result<Request<Bytes>> {
-    let (parts, body) = req.into_parts();
-    let body = body.collect().await?.to_bytes();
-    Ok(Request::from_parts(parts, body))
-}
+use hyper::Response;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 pub async fn response(res: Response<hyper::body::Incoming>) -> Result<Response<Bytes>, Upstream> {
     let (head, body) = res.into_parts();
     let body = body.collect().await.map_err(ReadBody)?;
     Ok(Response::from_parts(head, body.to_bytes()))
 }
+
+/// a body of which the beginning has already been read
+pub struct Prefixed<B> {
+    prefix: Option<Bytes>,
+    rest: B,
+}
+
+impl<B: Body<Data = Bytes> + Unpin> Body for Prefixed<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.prefix.take() {
+            Some(prefix) => Poll::Ready(Some(Ok(Frame::data(prefix)))),
+            None => Pin::new(&mut self.rest).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.prefix.is_none() && self.rest.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let prefix = self.prefix.as_ref().map_or(0, |p| p.len() as u64);
+        let rest = self.rest.size_hint();
+
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + prefix);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + prefix);
+        }
+        hint
+    }
+}
+
+/// a body, read up to a limit
+pub enum Slurped<B> {
+    /// the body was read completely
+    Complete(Bytes),
+    /// the body exceeds the limit, the part read so far is kept to still stream the whole body
+    TooLarge(Prefixed<B>),
+}
+
+/// read the body completely, unless it's larger than `limit` bytes
+pub async fn body<B: Body<Data = Bytes> + Unpin>(
+    mut body: B,
+    limit: usize,
+) -> Result<Slurped<B>, B::Error> {
+    let mut buffer = BytesMut::new();
+
+    while let Some(frame) = body.frame().await {
+        // trailers are dropped, like when collecting the body
+        if let Ok(data) = frame?.into_data() {
+            buffer.extend_from_slice(&data);
+
+            if buffer.len() > limit {
+                return Ok(Slurped::TooLarge(Prefixed {
+                    prefix: Some(buffer.freeze()),
+                    rest: body,
+                }));
+            }
+        }
+    }
+
+    Ok(Slurped::Complete(buffer.freeze()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{Prefixed, Slurped};
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    #[tokio::test]
+    async fn test_body_within_limit() {
+        let actual = super::body(Full::new(Bytes::from("hello")), 5)
+            .await
+            .unwrap();
+
+        assert!(matches!(actual, Slurped::Complete(b) if b == "hello"));
+    }
+
+    #[tokio::test]
+    async fn test_body_too_large() {
+        // a body of two frames
+        let body = Prefixed {
+            prefix: Some(Bytes::from("hello ")),
+            rest: Full::new(Bytes::from("world")),
+        };
+
+        let Slurped::TooLarge(prefixed) = super::body(body, 3).await.unwrap() else {
+            panic!("body must exceed the limit");
+        };
+
+        // nothing is lost, the whole body is still available
+        let actual = prefixed.collect().await.unwrap().to_bytes();
+        assert_eq!(actual, "hello world");
+    }
+}
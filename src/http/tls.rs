@@ -0,0 +1,16 @@
+use crate::http::error::Tls;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::Path;
+
+/// read all certificates from a PEM-file
+pub fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Tls> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect)
+        .map_err(|e| Tls::Pem(path.to_path_buf(), e))
+}
+
+/// read the (first) private key from a PEM-file
+pub fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, Tls> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| Tls::Pem(path.to_path_buf(), e))
+}
@@ -0,0 +1,61 @@
+use http::header::{CONNECTION, UPGRADE};
+use http::{HeaderMap, Request};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use tracing::{debug, error};
+
+/// check if the `Connection`-header(s) list the given option
+pub fn connection_has(headers: &HeaderMap, option: &str) -> bool {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|o| o.trim().eq_ignore_ascii_case(option))
+}
+
+/// check if the request asks to upgrade the connection, e.g. to a WebSocket
+pub fn is_requested<B>(req: &Request<B>) -> bool {
+    req.headers().contains_key(UPGRADE) && connection_has(req.headers(), "upgrade")
+}
+
+/// once both the client's and the upstream's connection are upgraded, copy data between them (in the background)
+pub fn tunnel(client: OnUpgrade, upstream: OnUpgrade) {
+    tokio::spawn(async move {
+        match tokio::try_join!(client, upstream) {
+            Ok((client, upstream)) => {
+                let mut client = TokioIo::new(client);
+                let mut upstream = TokioIo::new(upstream);
+
+                if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                    debug!("upgraded connection closed: {e:?}");
+                }
+            }
+            Err(e) => error!("error upgrading connection: {e:?}"),
+        }
+    });
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use bytes::Bytes;
+
+    fn request(headers: &[(&str, &str)]) -> http::Request<Bytes> {
+        let mut builder = http::Request::get("/ws");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Bytes::new()).unwrap()
+    }
+
+    #[test]
+    fn test_is_requested() {
+        assert!(super::is_requested(&request(&[
+            ("connection", "keep-alive, Upgrade"),
+            ("upgrade", "websocket"),
+        ])));
+        assert!(!super::is_requested(&request(&[("upgrade", "websocket")])));
+        assert!(!super::is_requested(&request(&[("connection", "upgrade")])));
+    }
+}
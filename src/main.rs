@@ -3,16 +3,20 @@ use anyhow::Context;
 use diff::dispatcher::Dispatcher;
 use diff::mirror::Mirror;
 use diff::publisher::Publisher;
+use std::sync::Arc;
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 use util::log;
 
 mod diff;
 mod domain;
 mod http;
 mod management;
+mod metrics;
 mod proxy;
+mod reload;
 mod settings;
 mod util;
 
@@ -28,19 +32,99 @@ async fn main() -> anyhow::Result<()> {
 
     info!("{settings:?}");
 
-    let publisher = Publisher::new(settings.config.kafka, settings.kafka_properties);
-    let mirror = Mirror::new(publisher);
-    let dispatcher = Dispatcher::new(
-        settings.config.reference.to_string(),
-        settings.config.candidate.to_string(),
-        settings.config.routes.as_slice(),
+    let dispatcher = Arc::new(Dispatcher::new(&settings.config).context("invalid config")?);
+    reload::watch(dispatcher.clone());
+    let kafka_options = diff::sink::kafka::Options {
+        equal_topic: settings.config.equal_samples.topic.clone(),
+        instance: settings.config.instance(),
+        cloudevents: settings.config.cloudevents.is_some(),
+    };
+    let sinks = diff::sink::from_settings(
+        &settings.config.sinks,
+        settings.config.kafka,
+        settings.kafka_properties,
+        kafka_options,
+    )
+    .context("invalid config")?;
+    let publisher = Publisher::new(
+        sinks,
+        settings.config.equal_samples,
+        settings.config.cloudevents.as_ref(),
+    )
+    .context("invalid config")?;
+    let timeouts = settings.config.timeouts;
+    let tls = &settings.config.tls;
+    let reference_client = http::client::new(timeouts.reference.connect, &tls.reference)
+        .context("building client for the reference")?;
+    let candidate_client = http::client::new(timeouts.candidate.connect, &tls.candidate)
+        .context("building client for the candidate")?;
+    let mirror = Mirror::new(publisher, candidate_client, reference_client.clone());
+
+    let proxy = proxy::Service::new(
+        dispatcher.clone(),
+        mirror.clone(),
+        reference_client,
+        settings.config.forwarding,
     );
 
-    let proxy = proxy::Service::new(dispatcher, mirror);
+    let server_tls = settings
+        .config
+        .server_tls
+        .as_ref()
+        .map(proxy::tls::acceptor)
+        .transpose()
+        .context("setting up TLS for the proxy")?;
+
+    let draining = CancellationToken::new();
+    let shutdown = CancellationToken::new();
+
+    let server = tokio::task::spawn(proxy::run(
+        settings.config.port,
+        proxy,
+        server_tls,
+        shutdown.clone(),
+    ));
+    let management = tokio::task::spawn(management::run(
+        settings.config.management_port,
+        draining.clone(),
+        dispatcher,
+        settings.config.management.token.clone(),
+    ));
+
+    tokio::select! {
+        result = management => result??,
+        () = shutdown_signal() => {}
+    }
 
-    tokio::task::spawn(proxy::run(settings.config.port, proxy));
+    // report not-ready first, so load-balancers stop routing traffic to this instance
+    info!("shutting down");
+    draining.cancel();
+    tokio::time::sleep(settings.config.shutdown.delay).await;
 
-    management::run(settings.config.management_port).await?;
+    let deadline = tokio::time::Instant::now() + settings.config.shutdown.timeout;
+    shutdown.cancel();
+    if tokio::time::timeout_at(deadline, server).await.is_err() {
+        warn!("in-flight requests still pending, not waiting any longer");
+    }
+    mirror.shutdown(deadline).await;
 
     Ok(())
 }
+
+/// wait for SIGTERM or ctrl-c
+async fn shutdown_signal() {
+    let terminate = async {
+        #[cfg(unix)]
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM-handler")
+            .recv()
+            .await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    };
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.expect("failed to install ctrl-c-handler"),
+        () = terminate => {}
+    }
+}
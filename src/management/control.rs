@@ -0,0 +1,106 @@
+use super::json_response;
+use crate::diff::dispatcher::Dispatcher;
+use crate::diff::error::Control;
+use bytes::Bytes;
+use http::{Method, Request, Response, StatusCode, header};
+use http_body_util::Full;
+use serde_json::json;
+use tracing::info;
+
+/// check the bearer-token, if configured
+pub fn is_authorized<B>(request: &Request<B>, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|actual| constant_time_eq(actual.as_bytes(), token.as_bytes()))
+}
+
+/// compare without returning early, so the token can't be guessed by timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// handle requests controlling the experiments, None if the request is not meant for this
+pub fn handle<B>(dispatcher: &Dispatcher, request: &Request<B>) -> Option<Response<Full<Bytes>>> {
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/routes") => Ok(()),
+        (&Method::POST, "/pause") => {
+            dispatcher.pause(true);
+            info!("paused all experiments");
+            Ok(())
+        }
+        (&Method::POST, "/resume") => {
+            dispatcher.pause(false);
+            info!("resumed all experiments");
+            Ok(())
+        }
+        (&Method::POST, "/routes/pause") => with_path(request, |path| {
+            dispatcher.pause_route(path, true)?;
+            info!("paused experiment for route {path}");
+            Ok(())
+        }),
+        (&Method::POST, "/routes/resume") => with_path(request, |path| {
+            dispatcher.pause_route(path, false)?;
+            info!("resumed experiment for route {path}");
+            Ok(())
+        }),
+        (&Method::POST, "/routes/sample-rate") => with_path(request, |path| {
+            let rate = param(request, "rate")
+                .and_then(|rate| rate.parse().ok())
+                .ok_or(Error::MissingParam("rate"))?;
+            dispatcher.set_sample_rate(path, rate)?;
+            info!("changed sample-rate for route {path} to {rate}");
+            Ok(())
+        }),
+        _ => return None,
+    };
+
+    Some(match result {
+        Ok(()) => json_response(
+            StatusCode::OK,
+            &json!({"paused": dispatcher.is_paused(), "routes": dispatcher.routes()}),
+        ),
+        Err(e) => json_response(e.status(), &json!({"error": e.to_string()})),
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("missing or invalid query-parameter: {0}")]
+    MissingParam(&'static str),
+
+    #[error(transparent)]
+    Control(#[from] Control),
+}
+
+impl Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::MissingParam(_) => StatusCode::BAD_REQUEST,
+            Error::Control(Control::UnknownRoute(_)) => StatusCode::NOT_FOUND,
+            Error::Control(Control::Invalid(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+/// the (URL-decoded) value of a query-parameter
+fn param<B>(request: &Request<B>, name: &str) -> Option<String> {
+    form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// run the action for the route given by the query-parameter `path`
+fn with_path<B>(
+    request: &Request<B>,
+    action: impl FnOnce(&str) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let path = param(request, "path").ok_or(Error::MissingParam("path"))?;
+    action(&path)
+}
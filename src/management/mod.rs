@@ -0,0 +1,230 @@
+use crate::diff::dispatcher::Dispatcher;
+use crate::{metrics, reload};
+use bytes::Bytes;
+use http::{Method, Request, Response, StatusCode, header};
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceBuilder;
+use tracing::error;
+
+mod control;
+
+/// everything the management-endpoints need
+struct State {
+    /// once cancelled, the health-endpoint reports miffy as not ready
+    draining: CancellationToken,
+    dispatcher: Arc<Dispatcher>,
+    /// if set, endpoints changing miffy's state require this bearer-token
+    token: Option<String>,
+}
+
+/// serve the management-endpoints. Once `draining` is cancelled, the health-endpoint reports miffy as not ready
+pub async fn run(
+    management_port: u16,
+    draining: CancellationToken,
+    dispatcher: Arc<Dispatcher>,
+    token: Option<String>,
+) -> tokio::io::Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], management_port));
+
+    let listener = TcpListener::bind(addr).await?;
+    let state = Arc::new(State {
+        draining,
+        dispatcher,
+        token,
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let io = TokioIo::new(stream);
+
+        let state = state.clone();
+        let svc = ServiceBuilder::new().service_fn(move |request: Request<Incoming>| {
+            let state = state.clone();
+            async move { Result::<_, Infallible>::Ok(handle(&state, &request).await) }
+        });
+        let svc = TowerToHyperService::new(svc);
+
+        tokio::task::spawn(async move {
+            if let Err(err) = http1::Builder::new().serve_connection(io, svc).await {
+                error!("Error serving connection: {err:?}");
+            }
+        });
+    }
+}
+
+async fn handle<B>(state: &State, request: &Request<B>) -> Response<Full<Bytes>> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/healthz") if state.draining.is_cancelled() => {
+            let mut unavailable =
+                Response::new(Full::new(Bytes::from(r#"{"status": "shutting down"}"#)));
+            *unavailable.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            unavailable
+        }
+        (&Method::GET, "/healthz") => {
+            Response::new(Full::new(Bytes::from(r#"{"status": "healthy"}"#)))
+        }
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Full::new(Bytes::from(metrics::render())))
+            .expect("static response must be valid"),
+        _ if !control::is_authorized(request, state.token.as_deref()) => {
+            let mut unauthorized = json_response(
+                StatusCode::UNAUTHORIZED,
+                &json!({"error": "missing or invalid bearer-token"}),
+            );
+            unauthorized.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                http::HeaderValue::from_static("Bearer"),
+            );
+            unauthorized
+        }
+        (&Method::POST, "/reload") => match reload::reload(&state.dispatcher).await {
+            Ok(()) => Response::new(Full::new(Bytes::from(r#"{"status": "reloaded"}"#))),
+            Err(e) => json_response(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &json!({"status": "invalid", "error": e.to_string()}),
+            ),
+        },
+        _ => control::handle(&state.dispatcher, request).unwrap_or_else(|| {
+            let mut not_found = Response::new(Full::new(Bytes::new()));
+            *not_found.status_mut() = StatusCode::NOT_FOUND;
+            not_found
+        }),
+    }
+}
+
+fn json_response(status: StatusCode, body: &serde_json::Value) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{State, handle};
+    use crate::diff::dispatcher::Dispatcher;
+    use crate::settings;
+    use http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use std::sync::Arc;
+    use tokio_util::sync::CancellationToken;
+
+    fn state(token: Option<&str>) -> State {
+        let config = settings::from_toml(
+            r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = [{ path = "/api/{value}" }, { path = "/user/{id}", sample_rate = 0.5 }]
+            "#,
+        );
+
+        State {
+            draining: CancellationToken::new(),
+            dispatcher: Arc::new(Dispatcher::new(&config).unwrap()),
+            token: token.map(str::to_string),
+        }
+    }
+
+    fn request(method: &str, uri: &str, token: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            builder = builder.header("authorization", format!("Bearer {token}"));
+        }
+        builder.body(()).unwrap()
+    }
+
+    async fn call(state: &State, request: &Request<()>) -> (StatusCode, serde_json::Value) {
+        let response = handle(state, request).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_control() {
+        let state = state(None);
+
+        let (status, body) = call(
+            &state,
+            &request("POST", "/routes/pause?path=%2Fapi%2F%7Bvalue%7D", None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["routes"][0]["paused"], true);
+        assert_eq!(body["routes"][1]["paused"], false);
+
+        let (status, body) = call(
+            &state,
+            &request("POST", "/routes/sample-rate?path=/user/{id}&rate=0.1", None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["routes"][1]["sample_rate"], 0.1);
+
+        let (status, body) = call(&state, &request("POST", "/pause", None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["paused"], true);
+        assert!(state.dispatcher.is_paused());
+
+        let (status, _) = call(
+            &state,
+            &request("POST", "/routes/pause?path=/unknown", None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(
+            &state,
+            &request("POST", "/routes/sample-rate?path=/user/{id}&rate=2", None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (status, _) = call(&state, &request("POST", "/routes/resume", None)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_token() {
+        let state = state(Some("secret"));
+
+        let (status, _) = call(&state, &request("GET", "/routes", None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&state, &request("GET", "/routes", Some("wrong"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = call(&state, &request("GET", "/routes", Some("secret"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["routes"][0]["path"], "/api/{value}");
+
+        // probes don't need the token
+        let (status, _) = call(&state, &request("GET", "/healthz", None)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_draining() {
+        let state = state(None);
+
+        let (status, body) = call(&state, &request("GET", "/healthz", None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "healthy");
+
+        state.draining.cancel();
+        let (status, body) = call(&state, &request("GET", "/healthz", None)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "shutting down");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::domain;
+use prometheus::{HistogramVec, IntCounterVec, IntGauge, TextEncoder};
+use std::sync::LazyLock;
+use std::time::Instant;
+
+/// requests handled by the proxy, by mode (`proxy` or `experiment`)
+pub static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_requests_total",
+        "requests handled by the proxy, by mode",
+        &["mode"]
+    )
+    .expect("metric must be valid")
+});
+
+/// number of experiments not mirrored, because too many mirror-tasks were in flight (`mirror_tasks`) or the
+/// request-body exceeded the limit (`body_size`)
+pub static DROPPED_EXPERIMENTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_dropped_experiments_total",
+        "experiments not mirrored, by route and reason",
+        &["route", "reason"]
+    )
+    .expect("metric must be valid")
+});
+
+/// reloads of the config, by result (`success` or `failure`)
+pub static CONFIG_RELOADS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_config_reloads_total",
+        "reloads of the config, by result",
+        &["result"]
+    )
+    .expect("metric must be valid")
+});
+
+/// compared samples, by route, candidate and result (`equal` or `different`)
+pub static SAMPLES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_samples_total",
+        "compared samples, by route, candidate and result",
+        &["route", "candidate", "result"]
+    )
+    .expect("metric must be valid")
+});
+
+/// failed requests to upstreams, by role and kind of error
+pub static UPSTREAM_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_upstream_errors_total",
+        "failed requests to upstreams, by role and kind of error",
+        &["role", "kind"]
+    )
+    .expect("metric must be valid")
+});
+
+/// duration of requests to upstreams, by role
+pub static UPSTREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    prometheus::register_histogram_vec!(
+        "miffy_upstream_duration_seconds",
+        "duration of requests to upstreams (including reading the body), by role",
+        &["role"]
+    )
+    .expect("metric must be valid")
+});
+
+/// deliveries to kafka, by result (`success` or `failure`)
+pub static KAFKA_DELIVERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "miffy_kafka_deliveries_total",
+        "deliveries of samples to kafka, by result",
+        &["result"]
+    )
+    .expect("metric must be valid")
+});
+
+/// currently running mirror-tasks
+pub static MIRROR_TASKS_IN_FLIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    prometheus::register_int_gauge!(
+        "miffy_mirror_tasks_in_flight",
+        "currently running mirror-tasks"
+    )
+    .expect("metric must be valid")
+});
+
+/// record the duration (and error, if any) of a request to an upstream with the given role
+pub fn observe_upstream(role: &str, start: Instant, error: Option<&domain::Error>) {
+    UPSTREAM_DURATION
+        .with_label_values(&[role])
+        .observe(start.elapsed().as_secs_f64());
+
+    if let Some(e) = error {
+        let kind: &'static str = e.into();
+        UPSTREAM_ERRORS.with_label_values(&[role, kind]).inc();
+    }
+}
+
+/// render all metrics in prometheus text-format
+pub fn render() -> String {
+    TextEncoder::new()
+        .encode_to_string(&prometheus::gather())
+        .expect("metrics must be encodable")
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_render() {
+        super::SAMPLES
+            .with_label_values(&["/api/{value}", "rewrite", "equal"])
+            .inc();
+
+        let actual = super::render();
+
+        assert!(actual.contains(
+            r#"miffy_samples_total{candidate="rewrite",result="equal",route="/api/{value}"}"#
+        ));
+    }
+}
@@ -1,32 +1,37 @@
 use crate::http::error::Upstream;
+use crate::http::{Body, full};
 use bytes::Bytes;
 use http::{Response, StatusCode};
-use http_body_util::Full;
+use http_body_util::{BodyExt, Full};
 use serde_json::json;
 use std::convert::Infallible;
 
 /// recover from errors by providing an error-response
 #[expect(clippy::unnecessary_wraps)]
-pub fn recover(err: Upstream) -> Result<Response<Full<Bytes>>, Infallible> {
-    Ok(err.into())
+pub fn recover(err: Upstream) -> Result<Response<Body>, Infallible> {
+    Ok(Response::<Full<Bytes>>::from(err).map(|b| b.map_err(|never| match never {}).boxed()))
 }
 
 /// generate a response if reading the incoming request to the proxy fails.
 /// These are typically TCP-errors (where the client is already gone), so returning a response is probably
 /// useless, but anyway
-#[expect(clippy::unnecessary_wraps)]
-pub fn handle_incoming_request(error: &hyper::Error) -> Result<Response<Full<Bytes>>, Infallible> {
-    Ok(Response::builder()
+pub fn handle_incoming_request(error: &hyper::Error) -> Response<Body> {
+    Response::builder()
         .status(StatusCode::BAD_REQUEST)
-        .body(error.to_string().into())
-        .expect("OK"))
+        .body(full(error.to_string().into()))
+        .expect("OK")
 }
 
 /// for a given upstream-error build a proper http-response that we can send to the client
 impl From<Upstream> for Response<Full<Bytes>> {
     fn from(value: Upstream) -> Self {
         let status = match value {
-            Upstream::ReadBody(_) | Upstream::Request(_) => StatusCode::BAD_GATEWAY,
+            // connect-timeouts are reported by the client as request-errors
+            Upstream::Request(_) if value.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            Upstream::ReadBody(_) | Upstream::Request(_) | Upstream::BodyTooLarge(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            Upstream::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             Upstream::InvalidUri(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
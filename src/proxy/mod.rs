@@ -2,6 +2,7 @@ pub mod error;
 pub mod log;
 mod run;
 mod service;
+pub mod tls;
 
 pub use run::run;
 pub use service::Service;
@@ -1,26 +1,61 @@
+use crate::http::headers::Peer;
 use crate::proxy;
 use crate::proxy::error::recover;
-use hyper::server::conn::http1;
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
 use hyper_util::service::TowerToHyperService;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tokio_util::sync::CancellationToken;
 use tower::ServiceBuilder;
-use tracing::error;
+use tracing::{debug, error, info};
 
-/// run the main server loop
-pub async fn run(port: u16, proxy: proxy::Service) -> tokio::io::Result<()> {
-    let proxy = Arc::new(proxy);
+/// max. duration for clients to complete the TLS-handshake
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// run the main server loop, until `shutdown` is cancelled. Then stop accepting connections and wait for in-flight
+/// requests to finish.
+///
+/// Serves HTTP/1.1 and HTTP/2 (with prior knowledge, or negotiated via ALPN if TLS is terminated)
+pub async fn run(
+    port: u16,
+    proxy: proxy::Service,
+    tls: Option<TlsAcceptor>,
+    shutdown: CancellationToken,
+) -> tokio::io::Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = TcpListener::bind(addr).await?;
 
+    serve(listener, proxy, tls, shutdown).await
+}
+
+/// serve connections accepted by the listener, until `shutdown` is cancelled
+async fn serve(
+    listener: TcpListener,
+    proxy: proxy::Service,
+    tls: Option<TlsAcceptor>,
+    shutdown: CancellationToken,
+) -> tokio::io::Result<()> {
+    let proxy = Arc::new(proxy);
+
     let trace_layer = proxy::log::new_trace_layer();
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
 
     loop {
-        let (stream, _) = listener.accept().await?;
-        let io = TokioIo::new(stream);
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            () = shutdown.cancelled() => break,
+        };
+        let peer = Peer {
+            addr,
+            tls: tls.is_some(),
+        };
 
         let proxy = proxy.clone();
 
@@ -28,19 +63,297 @@ pub async fn run(port: u16, proxy: proxy::Service) -> tokio::io::Result<()> {
             .layer(trace_layer.clone())
             .service_fn(move |request| {
                 let proxy = proxy.clone();
-                async move {
-                    match crate::http::slurp::request(request).await {
-                        Ok(request) => proxy.handle(request).await.or_else(recover),
-                        Err(e) => proxy::error::handle_incoming_request(&e),
-                    }
-                }
+                async move { proxy.handle(request, peer).await.or_else(recover) }
             });
         let svc = TowerToHyperService::new(svc);
 
+        let builder = builder.clone();
+        let tls = tls.clone();
+        let watcher = graceful.watcher();
+        let shutdown = shutdown.clone();
         tokio::task::spawn(async move {
-            if let Err(err) = http1::Builder::new().serve_connection(io, svc).await {
+            let result = match tls {
+                Some(tls) => {
+                    let Some(stream) =
+                        handshake(&tls, stream, TLS_HANDSHAKE_TIMEOUT, &shutdown).await
+                    else {
+                        return;
+                    };
+                    watcher
+                        .watch(builder.serve_connection_with_upgrades(TokioIo::new(stream), svc))
+                        .await
+                }
+                None => {
+                    watcher
+                        .watch(builder.serve_connection_with_upgrades(TokioIo::new(stream), svc))
+                        .await
+                }
+            };
+
+            if let Err(err) = result {
                 error!("Error serving connection: {err:?}");
             }
         });
     }
+
+    drop(listener);
+    info!("draining {} connections", graceful.count());
+    graceful.shutdown().await;
+
+    Ok(())
+}
+
+/// terminate TLS, unless the client doesn't complete the handshake in time, or the server shuts down meanwhile
+async fn handshake(
+    tls: &TlsAcceptor,
+    stream: TcpStream,
+    timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Option<TlsStream<TcpStream>> {
+    tokio::select! {
+        result = tokio::time::timeout(timeout, tls.accept(stream)) => match result {
+            Ok(Ok(stream)) => Some(stream),
+            Ok(Err(e)) => {
+                debug!("TLS-handshake failed: {e:?}");
+                None
+            }
+            Err(_) => {
+                debug!("TLS-handshake timed out");
+                None
+            }
+        },
+        () = shutdown.cancelled() => None,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use crate::diff::dispatcher::Dispatcher;
+    use crate::diff::mirror::Mirror;
+    use crate::diff::publisher::Publisher;
+    use crate::http::client::{self, UpstreamExt};
+    use crate::proxy;
+    use crate::proxy::tls;
+    use crate::settings::{self, Forwarding, ServerTls, Timeouts, Tls};
+    use crate::util::temp_dir::TempDir;
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use rustls::RootCertStore;
+    use rustls_pki_types::{PrivateKeyDer, ServerName};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+    use tokio_util::sync::CancellationToken;
+
+    /// a reference responding with "ok" after the given delay
+    async fn slow_reference(delay: Duration) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = service_fn(move |_| async move {
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, Infallible>(http::Response::new(Full::new(Bytes::from("ok"))))
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        addr
+    }
+
+    /// the proxy, without any routes, i.e. just proxying to the reference
+    fn service(reference: SocketAddr) -> proxy::Service {
+        let config = settings::from_toml(&format!(
+            r#"
+            reference = "http://{reference}"
+            candidate = "http://candidate"
+            routes = []
+            "#
+        ));
+        let client = client::new(None, &Tls::default()).unwrap();
+        let publisher = Publisher::new(vec![], config.equal_samples.clone(), None).unwrap();
+        proxy::Service::new(
+            Arc::new(Dispatcher::new(&config).unwrap()),
+            Mirror::new(publisher, client.clone(), client.clone()),
+            client,
+            Forwarding::default(),
+        )
+    }
+
+    /// send a GET-request to the URI via HTTP/2 over the stream, return the version and body of the response
+    async fn get_via_http2<S>(stream: S, uri: &str) -> (http::Version, Bytes)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        let request = http::Request::get(uri)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        let version = response.version();
+        (
+            version,
+            response.into_body().collect().await.unwrap().to_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_http2_with_prior_knowledge() {
+        let reference = slow_reference(Duration::ZERO).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(super::serve(
+            listener,
+            service(reference),
+            None,
+            shutdown.clone(),
+        ));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (version, body) = get_via_http2(stream, &format!("http://{addr}/")).await;
+        assert_eq!(version, http::Version::HTTP_2);
+        assert_eq!(body, "ok");
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_http2_via_alpn() {
+        let reference = slow_reference(Duration::ZERO).await;
+        let dir = TempDir::new("alpn");
+        let settings = ServerTls {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+        };
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(&settings.cert, generated.cert.pem()).unwrap();
+        std::fs::write(&settings.key, generated.signing_key.serialize_pem()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(super::serve(
+            listener,
+            service(reference),
+            Some(tls::acceptor(&settings).unwrap()),
+            shutdown.clone(),
+        ));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(generated.cert.der().clone()).unwrap();
+        let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(
+                ServerName::try_from("localhost").unwrap(),
+                TcpStream::connect(addr).await.unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let (version, body) =
+            get_via_http2(stream, &format!("https://localhost:{}/", addr.port())).await;
+        assert_eq!(version, http::Version::HTTP_2);
+        assert_eq!(body, "ok");
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake_timeout() {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![generated.cert.der().clone()],
+            PrivateKeyDer::try_from(generated.signing_key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+
+        // clients connecting, but never starting the handshake
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let handshake = super::handshake(&acceptor, stream, Duration::from_millis(50), &shutdown);
+        assert!(handshake.await.is_none());
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        shutdown.cancel();
+        let handshake = super::handshake(&acceptor, stream, Duration::from_secs(60), &shutdown);
+        let aborted = tokio::time::timeout(Duration::from_secs(1), handshake).await;
+        assert!(aborted.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        let reference = slow_reference(Duration::from_millis(300)).await;
+        let client = client::new(None, &Tls::default()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(super::serve(
+            listener,
+            service(reference),
+            None,
+            shutdown.clone(),
+        ));
+
+        let in_flight = tokio::spawn(async move {
+            client
+                .upstream(
+                    http::Request::new(Bytes::new()),
+                    &format!("http://{addr}/"),
+                    &Timeouts::default(),
+                    None,
+                )
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.cancel();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // no new connections are accepted
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        // but the in-flight request is finished
+        let response = in_flight.await.unwrap().unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.body(), "ok");
+
+        tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
 }
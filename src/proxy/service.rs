@@ -1,32 +1,44 @@
 use crate::diff::dispatcher::Dispatcher;
 use crate::diff::mirror::Mirror;
 use crate::diff::tx_ext::TxExt;
-use crate::http::client::{Client, UpstreamExt};
-use crate::http::model::RequestMode;
-use crate::http::{SHADOW_TEST_HEADER, error};
-use http::HeaderValue;
-use http_body_util::Full;
-use hyper::body::Bytes;
+use crate::http::client::{self, Client, UpstreamExt};
+use crate::http::headers::{self, Peer};
+use crate::http::model::{Experiment, RequestContext, RequestMode};
+use crate::http::slurp::{self, Slurped};
+use crate::http::{Body, SHADOW_TEST_HEADER, error, full, upgrade};
+use crate::settings::Forwarding;
+use crate::{domain, metrics, proxy};
+use http::request::Parts;
+use http::{HeaderValue, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::{Bytes, Incoming};
 use hyper::{Request, Response};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::debug;
 
 const SHADOW_TEST_ROLE_REFERENCE: HeaderValue = HeaderValue::from_static("reference");
 const SHADOW_TEST_ROLE_UPSTREAM: HeaderValue = HeaderValue::from_static("upstream");
 
 pub struct Service {
     client: Client,
-    dispatcher: Dispatcher,
+    dispatcher: Arc<Dispatcher>,
     mirror: Mirror,
+    forwarding: Forwarding,
 }
 
 impl Service {
-    pub fn new(dispatcher: Dispatcher, mirror: Mirror) -> Self {
+    pub fn new(
+        dispatcher: Arc<Dispatcher>,
+        mirror: Mirror,
+        client: Client,
+        forwarding: Forwarding,
+    ) -> Self {
         Self {
             dispatcher,
-            client: hyper_util::client::legacy::Client::builder(
-                hyper_util::rt::TokioExecutor::new(),
-            )
-            .build_http(),
+            client,
             mirror,
+            forwarding,
         }
     }
 
@@ -34,25 +46,155 @@ impl Service {
     /// this runs for any request (to get the reference), so it tries to do as little as possible
     pub async fn handle(
         &self,
-        mut req: Request<Bytes>,
-    ) -> Result<Response<Full<Bytes>>, error::Upstream> {
-        let context = self.dispatcher.init_context(&req);
+        mut req: Request<Incoming>,
+        peer: Peer,
+    ) -> Result<Response<Body>, error::Upstream> {
+        let mut context = self.dispatcher.init_context(&req);
+        // before anything else, so reference and candidate get the same headers
+        headers::add_forwarded(&mut req, peer, &self.forwarding);
+
+        if upgrade::is_requested(&req) {
+            let client_upgrade = hyper::upgrade::on(&mut req);
+            let (parts, body) = req.into_parts();
+            let mut response = self.proxy(parts, body.boxed(), &context).await?;
+
+            if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+                upgrade::tunnel(client_upgrade, hyper::upgrade::on(&mut response));
+            }
+            return Ok(response);
+        }
+
+        let (parts, body) = req.into_parts();
 
-        // determine the Role-header
-        let role = match &context.mode {
-            RequestMode::Proxy => SHADOW_TEST_ROLE_UPSTREAM,
-            RequestMode::Experiment { .. } => SHADOW_TEST_ROLE_REFERENCE,
+        let RequestMode::Experiment(experiment) =
+            std::mem::replace(&mut context.mode, RequestMode::Proxy)
+        else {
+            return self.proxy(parts, body.boxed(), &context).await;
         };
 
-        // conditionally spawn a mirror-task
-        self.mirror.spawn(context.mode);
+        // only requests under test are buffered (to send them to the candidate), up to a limit
+        let body = match slurp::body(body, experiment.max_body_size).await {
+            Ok(Slurped::Complete(body)) => body,
+            Ok(Slurped::TooLarge(body)) => {
+                metrics::DROPPED_EXPERIMENTS
+                    .with_label_values(&[&experiment.route, "body_size"])
+                    .inc();
+                debug!(
+                    "request-body exceeds {} bytes, not mirroring request to {}",
+                    experiment.max_body_size, experiment.route
+                );
+                return self.proxy(parts, body.boxed(), &context).await;
+            }
+            Err(e) => return Ok(proxy::error::handle_incoming_request(&e)),
+        };
+
+        self.experiment(Request::from_parts(parts, body), context, *experiment)
+            .await
+    }
+
+    /// simply proxy the request to the reference, streaming request- and response-body
+    async fn proxy(
+        &self,
+        mut parts: Parts,
+        body: Body,
+        context: &RequestContext,
+    ) -> Result<Response<Body>, error::Upstream> {
+        metrics::REQUESTS.with_label_values(&["proxy"]).inc();
+
+        parts
+            .headers
+            .insert(SHADOW_TEST_HEADER, SHADOW_TEST_ROLE_UPSTREAM);
+        let start = Instant::now();
+        let response = self
+            .client
+            .send(
+                Request::from_parts(parts, body),
+                &context.reference_uri,
+                &context.reference_timeouts,
+            )
+            .await;
+        let error = response.as_ref().err().map(domain::Error::from);
+        metrics::observe_upstream("reference", start, error.as_ref());
+
+        response.map(|r| r.map(BodyExt::boxed))
+    }
+
+    /// send the request to the reference, and spawn a mirror-task to send it to the candidate
+    async fn experiment(
+        &self,
+        mut req: Request<Bytes>,
+        context: RequestContext,
+        experiment: Experiment,
+    ) -> Result<Response<Body>, error::Upstream> {
+        metrics::REQUESTS.with_label_values(&["experiment"]).inc();
 
-        req.headers_mut().insert(SHADOW_TEST_HEADER, role);
-        let response = self.client.upstream(req, &context.reference_uri).await;
+        let max_body_size = experiment.max_body_size;
+        let route = experiment.route.clone();
+        let captures_sent = experiment.headers.captures_sent();
+        self.mirror.spawn(experiment, req.clone());
 
-        // send the reference-response over to the candidate-task
-        context.tx.send_reference(context.reference_uri, &response);
+        req.headers_mut()
+            .insert(SHADOW_TEST_HEADER, SHADOW_TEST_ROLE_REFERENCE);
+        let sent_headers = captures_sent.then(|| {
+            self.client
+                .sent_headers(req.headers(), &context.reference_uri)
+        });
+        let start = Instant::now();
+        let mut ttfb = None;
+        let response = match self
+            .client
+            .send(
+                req.map(full),
+                &context.reference_uri,
+                &context.reference_timeouts,
+            )
+            .await
+        {
+            Ok(response) => {
+                ttfb = Some(start.elapsed());
+                client::read_body(response, &context.reference_timeouts, max_body_size).await
+            }
+            Err(e) => Err(e),
+        };
+        let latency = domain::Latency {
+            ttfb,
+            total: start.elapsed(),
+        };
+        let error = response.as_ref().err().map(domain::Error::from);
+        metrics::observe_upstream("reference", start, error.as_ref());
 
-        response.map(|r| r.map(Full::new))
+        let (head, body) = match response {
+            Ok(response) => response.into_parts(),
+            Err(e) => {
+                let response = Err(e);
+                context
+                    .tx
+                    .send_reference(context.reference_uri, sent_headers, latency, &response);
+                return response.map(|r| r.map(full));
+            }
+        };
+
+        match body {
+            Slurped::Complete(body) => {
+                let response = Ok(Response::from_parts(head, body));
+                // send the reference-response over to the candidate-task
+                context
+                    .tx
+                    .send_reference(context.reference_uri, sent_headers, latency, &response);
+                response.map(|r| r.map(full))
+            }
+            Slurped::TooLarge(body) => {
+                // still respond with the whole body, but there's nothing to compare: drop the sender, so no sample is
+                // published
+                drop(context.tx);
+                metrics::DROPPED_EXPERIMENTS
+                    .with_label_values(&[&route, "body_size"])
+                    .inc();
+                debug!(
+                    "response-body of the reference exceeds {max_body_size} bytes, not publishing a sample for {route}"
+                );
+                Ok(Response::from_parts(head, body.boxed()))
+            }
+        }
     }
 }
@@ -0,0 +1,134 @@
+use crate::http::error::Tls;
+use crate::http::tls::{read_certs, read_key};
+use crate::settings::ServerTls;
+use crate::util::watch;
+use rustls::ServerConfig;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info};
+
+/// how often to check the certificate-files for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// resolves the configured server-certificate, which may be reloaded at runtime
+#[derive(Debug)]
+struct Resolver {
+    settings: ServerTls,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Resolver {
+    fn new(settings: ServerTls, provider: Arc<CryptoProvider>) -> Result<Self, Tls> {
+        let current = RwLock::new(Arc::new(Self::load(&settings, &provider)?));
+        Ok(Self {
+            settings,
+            provider,
+            current,
+        })
+    }
+
+    fn load(settings: &ServerTls, provider: &CryptoProvider) -> Result<CertifiedKey, Tls> {
+        Ok(CertifiedKey::from_der(
+            read_certs(&settings.cert)?,
+            read_key(&settings.key)?,
+            provider,
+        )?)
+    }
+
+    /// reload the certificate from the files. Keeps the current certificate if the files are invalid
+    fn reload(&self) {
+        match Self::load(&self.settings, &self.provider) {
+            Ok(key) => {
+                *self.current.write().expect("certificate-lock poisoned") = Arc::new(key);
+                info!("reloaded certificate {}", self.settings.cert.display());
+            }
+            Err(e) => error!("error reloading certificate, keeping the current one: {e}"),
+        }
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        self.current
+            .read()
+            .expect("certificate-lock poisoned")
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// build an acceptor to terminate TLS with the given certificate, negotiating HTTP/2 or HTTP/1.1 via ALPN.
+///
+/// The certificate is reloaded whenever its files change.
+pub fn acceptor(settings: &ServerTls) -> Result<TlsAcceptor, Tls> {
+    let provider = Arc::new(ring::default_provider());
+    let resolver = Arc::new(Resolver::new(settings.clone(), provider.clone())?);
+
+    watch::spawn(
+        vec![settings.cert.clone(), settings.key.clone()],
+        RELOAD_INTERVAL,
+        {
+            let resolver = resolver.clone();
+            move || resolver.reload()
+        },
+    );
+
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::Resolver;
+    use crate::settings::ServerTls;
+    use crate::util::temp_dir::TempDir;
+    use rcgen::{CertifiedKey, generate_simple_self_signed};
+    use std::sync::Arc;
+
+    fn write(settings: &ServerTls, name: &str) -> CertifiedKey<rcgen::KeyPair> {
+        let generated = generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        std::fs::write(&settings.cert, generated.cert.pem()).unwrap();
+        std::fs::write(&settings.key, generated.signing_key.serialize_pem()).unwrap();
+        generated
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = TempDir::new("server-tls");
+        let settings = ServerTls {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+        };
+
+        let first = write(&settings, "first.example.com");
+        let resolver = Resolver::new(
+            settings.clone(),
+            Arc::new(rustls::crypto::ring::default_provider()),
+        )
+        .unwrap();
+        assert_eq!(&resolver.current().cert[0], first.cert.der());
+
+        let second = write(&settings, "second.example.com");
+        resolver.reload();
+        assert_eq!(&resolver.current().cert[0], second.cert.der());
+
+        // invalid files are ignored
+        std::fs::write(&settings.key, "invalid").unwrap();
+        resolver.reload();
+        assert_eq!(&resolver.current().cert[0], second.cert.der());
+    }
+}
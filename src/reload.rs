@@ -0,0 +1,67 @@
+use crate::diff::dispatcher::Dispatcher;
+use crate::diff::error::InvalidConfig;
+use crate::metrics;
+use crate::settings::{self, Setting};
+use crate::util::watch;
+use config::ConfigError;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info, warn};
+
+/// how often to check the config-file for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("error reading config: {0}")]
+    Read(#[from] ConfigError),
+
+    #[error("error reading config: {0}")]
+    Task(#[from] JoinError),
+
+    #[error(transparent)]
+    Invalid(#[from] InvalidConfig),
+}
+
+/// read the config again and swap routes, upstreams and comparison-rules of the dispatcher.
+/// Keeps the current config if the new one is invalid
+pub async fn reload(dispatcher: &Dispatcher) -> Result<(), Error> {
+    // reading the config-files blocks
+    let result = match tokio::task::spawn_blocking(Setting::emerge).await {
+        Ok(settings) => settings
+            .map_err(Error::from)
+            .and_then(|settings| Ok(dispatcher.reload(&settings.config)?)),
+        Err(e) => Err(e.into()),
+    };
+
+    match &result {
+        Ok(()) => info!("reloaded config"),
+        Err(e) => error!("error reloading config, keeping the current one: {e}"),
+    }
+    metrics::CONFIG_RELOADS
+        .with_label_values(&[if result.is_ok() { "success" } else { "failure" }])
+        .inc();
+
+    result
+}
+
+/// reload the config whenever the config-file changes
+pub fn watch(dispatcher: Arc<Dispatcher>) -> JoinHandle<()> {
+    let path = settings::config_path();
+    if !path.is_file() {
+        warn!(
+            "config-file {} not found, not reloading on changes",
+            path.display()
+        );
+    }
+
+    watch::spawn(vec![path], WATCH_INTERVAL, move || {
+        let dispatcher = dispatcher.clone();
+        tokio::spawn(async move {
+            // errors are logged already
+            let _ = reload(&dispatcher).await;
+        });
+    })
+}
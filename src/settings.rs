@@ -1,5 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::util::json_path::JsonPath;
 use crate::util::log;
 use config::{ConfigError, Environment, File, FileFormat};
 use serde::Deserialize;
@@ -45,26 +48,442 @@ pub struct Kafka {
     pub properties: HashMap<String, KafkaPropertyValue>,
 }
 
+/// a destination to publish samples to
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Sink {
+    /// publish to kafka, as configured in section `kafka`
+    Kafka,
+    /// print to stdout, one sample per line
+    Stdout,
+    /// append to a file, one sample per line (JSONL)
+    File { path: PathBuf },
+    /// POST each sample to the given URL
+    Webhook {
+        url: String,
+        /// max. duration to connect, to receive the response-head and to read the response-body, each
+        #[serde(default = "default_webhook_timeout", with = "humantime_serde")]
+        timeout: Duration,
+    },
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// samples of equal responses are not published by default. Publishing a fraction of them tells apart routes without
+/// differences from routes that are not exercised at all
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct EqualSamples {
+    /// fraction (0.0–1.0) of equal samples to publish
+    pub rate: f64,
+
+    /// publish compact match-records instead of whole samples
+    pub compact: bool,
+
+    /// kafka-topic to publish equal samples to, instead of `kafka.topic`
+    pub topic: Option<String>,
+}
+
+/// CloudEvents-envelope (structured mode, JSON) of published samples
+#[derive(Debug, Deserialize, Clone)]
+pub struct CloudEvents {
+    /// `source` of the events, e.g. "/miffy/checkout"
+    pub source: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub kafka: Kafka,
 
+    /// where to publish samples to
+    pub sinks: Vec<Sink>,
+
+    /// which samples of equal responses to publish
+    pub equal_samples: EqualSamples,
+
+    /// if set, wrap published samples in a CloudEvents-envelope
+    pub cloudevents: Option<CloudEvents>,
+
+    /// identifies this instance in published samples, defaults to the hostname
+    pub instance: Option<String>,
+
     /// default reference URL to use
     pub reference: String,
     /// default candidate URL to use
-    pub candidate: String,
+    pub candidate: Option<String>,
+    /// default named candidates, in addition to `candidate`. Requests under test are sent to all of them
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    /// default URL of a second instance of the reference, to detect noise (non-deterministic values)
+    pub secondary_reference: Option<String>,
 
     /// port to listen to
     pub port: u16,
 
     pub management_port: u16,
 
+    /// settings of the management-endpoints
+    #[serde(default)]
+    pub management: Management,
+
     /// format to log.
     pub logging: log::Format,
 
+    /// JSON-paths to ignore when comparing JSON bodies, for all routes
+    #[serde(default)]
+    pub ignore: Vec<JsonPath>,
+
+    /// which response-headers to compare, for all routes
+    #[serde(default)]
+    pub headers: HeaderComparison,
+
+    /// how much slower than the reference the candidate may be, for all routes
+    #[serde(default)]
+    pub latency_budget: LatencyBudget,
+
+    /// timeouts for requests to reference and candidate
+    #[serde(default)]
+    pub timeouts: UpstreamTimeouts,
+
+    /// TLS-settings for connections to reference and candidate
+    #[serde(default)]
+    pub tls: UpstreamTls,
+
+    /// headers to add to requests to reference and candidate, to tell them about the client
+    #[serde(default)]
+    pub forwarding: Forwarding,
+
+    /// terminate TLS on the proxy-port, plain HTTP if not set
+    pub server_tls: Option<ServerTls>,
+
+    /// how to shut down gracefully
+    pub shutdown: Shutdown,
+
+    /// which request-headers to include in samples, and how to redact them
+    pub request_headers: RequestHeaders,
+
+    /// values to redact from request- and response-bodies before comparing and publishing, for all routes
+    pub redact_body: BodyRedaction,
+
+    /// max. number of in-flight mirror-tasks (for all routes). Requests exceeding the limit are not mirrored
+    pub max_mirror_tasks: Option<usize>,
+
+    /// max. size (in bytes) of request- and response-bodies to buffer for experiments. Plain proxied requests are
+    /// streamed, regardless of their size
+    pub max_body_size: usize,
+
+    /// fraction (0.0–1.0) of requests matching a route to mirror
+    pub sample_rate: f64,
+
+    /// optional value to derive the sampling-decision from, instead of randomly
+    pub sample_by: Option<SampleBy>,
+
     pub routes: Vec<Route>,
 }
 
+impl Config {
+    /// identifies this instance: `instance` if configured, else the hostname
+    pub fn instance(&self) -> String {
+        self.instance
+            .clone()
+            .or_else(hostname)
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// the hostname, `HOSTNAME` is usually set by shells and container-runtimes, but not exported by all of them
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .into_iter()
+        .chain(
+            ["/proc/sys/kernel/hostname", "/etc/hostname"]
+                .iter()
+                .filter_map(|path| std::fs::read_to_string(path).ok()),
+        )
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct Management {
+    /// if set, endpoints changing miffy's state (e.g. reload, pause) require this bearer-token
+    pub token: Option<String>,
+}
+
+impl std::fmt::Debug for Management {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't log the token
+        f.debug_struct("Management")
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// timeouts for requests to an upstream. No timeout if not set
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Timeouts {
+    /// max. duration to establish a connection
+    #[serde(default, with = "humantime_serde")]
+    pub connect: Option<Duration>,
+
+    /// max. duration to receive the response-head, including establishing a connection
+    #[serde(default, with = "humantime_serde")]
+    pub response: Option<Duration>,
+
+    /// max. duration to read the response-body
+    #[serde(default, with = "humantime_serde")]
+    pub body: Option<Duration>,
+}
+
+impl Timeouts {
+    /// use the values of this timeouts, falling back to the values of the other one
+    pub fn or(self, fallback: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(fallback.connect),
+            response: self.response.or(fallback.response),
+            body: self.body.or(fallback.body),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct UpstreamTimeouts {
+    #[serde(default)]
+    pub reference: Timeouts,
+
+    #[serde(default)]
+    pub candidate: Timeouts,
+}
+
+/// TLS-settings for connections to an upstream, only relevant for https-URLs
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct Tls {
+    /// PEM-file with CA-certificates to trust instead of the built-in (webpki) roots
+    pub ca: Option<PathBuf>,
+
+    /// PEM-file with a client-certificate (chain) to authenticate with (mTLS)
+    pub cert: Option<PathBuf>,
+
+    /// PEM-file with the private key of the client-certificate
+    pub key: Option<PathBuf>,
+
+    /// server-name to use for SNI and to verify the server-certificate, instead of the URL's host
+    pub server_name: Option<String>,
+
+    /// value of the Host-header to send, instead of the Host-header of the original request
+    pub host: Option<String>,
+
+    /// `server_name` and `host` of single upstreams, instead of the ones above
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamNames>,
+}
+
+/// server-name and Host-header of a single upstream
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct UpstreamNames {
+    /// base-URL of the upstream, as configured for the reference or a candidate
+    pub url: String,
+
+    pub server_name: Option<String>,
+
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct UpstreamTls {
+    #[serde(default)]
+    pub reference: Tls,
+
+    #[serde(default)]
+    pub candidate: Tls,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Forwarding {
+    /// add `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+    #[serde(default)]
+    pub x_forwarded: bool,
+
+    /// add `Forwarded` (RFC 7239)
+    #[serde(default)]
+    pub forwarded: bool,
+}
+
+/// certificate to terminate TLS with. Reloaded when the files change
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ServerTls {
+    /// PEM-file with the server-certificate (chain)
+    pub cert: PathBuf,
+
+    /// PEM-file with the private key of the server-certificate
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct Shutdown {
+    /// duration between reporting not-ready and to stop accepting connections, so load-balancers stop routing traffic
+    #[serde(with = "humantime_serde")]
+    pub delay: Duration,
+
+    /// max. duration to drain in-flight requests, pending mirror-tasks and sinks
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+/// value to derive the sampling-decision from, so the same value is always sampled the same way
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleBy {
+    /// name of a route-parameter
+    Param(String),
+    /// name of a request-header
+    Header(String),
+}
+
+/// how to redact sensitive values before publishing samples
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Redaction {
+    /// replace the value by `***`
+    #[default]
+    #[serde(alias = "mask")]
+    Replace,
+    /// replace the value by its SHA-256-hash, so equal values can still be correlated
+    Hash,
+    /// remove the value
+    Drop,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RequestHeaders {
+    /// include the headers of the original request in samples
+    pub capture: bool,
+
+    /// include the headers as sent to reference and candidate in samples
+    pub capture_sent: bool,
+
+    /// how to redact the headers listed in `redact`
+    pub redaction: Redaction,
+
+    /// names of headers to redact (case-insensitive)
+    pub redact: Vec<String>,
+}
+
+/// well-known patterns of sensitive values
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// e-mail-addresses
+    Email,
+    /// international bank account numbers, with or without spaces
+    Iban,
+    /// JSON web tokens
+    Jwt,
+}
+
+/// values to redact from bodies. JSON-paths apply to JSON bodies, patterns to all strings of JSON bodies and to textual
+/// (UTF-8) bodies
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BodyRedaction {
+    /// JSON-paths of values to redact
+    #[serde(default)]
+    pub paths: Vec<JsonPath>,
+
+    /// well-known patterns to redact
+    #[serde(default)]
+    pub patterns: Vec<Pattern>,
+
+    /// regular expressions to redact
+    #[serde(default)]
+    pub regex: Vec<String>,
+
+    /// how to redact, for routes: optional redaction to use instead of the global one
+    pub redaction: Option<Redaction>,
+}
+
+/// a candidate, identified by its name in samples and kafka-keys
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Candidate {
+    pub name: String,
+    pub url: String,
+}
+
+/// conditions on a request, values are compared exactly, `*` matches any value
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Conditions {
+    /// header-names (case-insensitive) and values
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// names and (URL-decoded) values of query-parameters
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+}
+
+/// how to rewrite requests to the candidate(s) of a route
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Rewrite {
+    /// path-template, `{name}` is replaced with the value of the route-parameter `name`. Original path if not set
+    pub path: Option<String>,
+
+    #[serde(default)]
+    pub query: QueryRewrite,
+
+    #[serde(default)]
+    pub headers: HeaderRewrite,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct QueryRewrite {
+    /// names and values of query-parameters to add, replacing existing values
+    #[serde(default)]
+    pub add: BTreeMap<String, String>,
+
+    /// names of query-parameters to remove
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HeaderRewrite {
+    /// header-names and values to set, replacing existing values
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+
+    /// header-names and values to replace, only if the header is present
+    #[serde(default)]
+    pub replace: BTreeMap<String, String>,
+
+    /// names of headers to remove
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HeaderComparison {
+    /// names of headers to compare (case-insensitive), `*` to compare all headers
+    #[serde(default)]
+    pub compare: Vec<String>,
+
+    /// names of headers to never compare, even if matched by `compare`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+/// how much slower (in total duration) than the reference the candidate may be. If both limits are set, exceeding
+/// either is enough to be considered different. No budget if none is set
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct LatencyBudget {
+    /// max. duration the candidate may be slower, e.g. "50ms"
+    #[serde(default, with = "humantime_serde")]
+    pub absolute: Option<Duration>,
+
+    /// max. fraction of the reference's duration the candidate may be slower, e.g. 0.2 for 20%
+    pub relative: Option<f64>,
+}
+
 #[derive(Debug)]
 pub struct Setting {
     pub config: Config,
@@ -79,16 +498,66 @@ pub struct Route {
     /// name of the param (from the route) to use as
     pub key: Option<String>,
 
+    /// methods to mirror (case-insensitive), all methods if empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    /// conditions a request must meet (all of them) to be mirrored
+    #[serde(default)]
+    pub require: Conditions,
+
+    /// conditions a request must not meet (any of them) to be mirrored
+    #[serde(default)]
+    pub exclude: Conditions,
+
     /// optional reference URL to use instead of the default-url
     pub reference: Option<String>,
 
-    /// optional candidate URL to use instead of the default-url
+    /// optional candidate URL to use instead of the default candidate(s)
     pub candidate: Option<String>,
+
+    /// optional named candidates to use instead of the default candidate(s), in addition to `candidate`
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+
+    /// optional secondary reference URL to use instead of the default-url
+    pub secondary_reference: Option<String>,
+
+    /// how to rewrite requests to the candidate(s)
+    #[serde(default)]
+    pub rewrite: Rewrite,
+
+    /// JSON-paths to ignore when comparing JSON bodies, in addition to the global ones
+    #[serde(default)]
+    pub ignore: Vec<JsonPath>,
+
+    /// values to redact from bodies, in addition to the global ones
+    #[serde(default)]
+    pub redact_body: BodyRedaction,
+
+    /// optional header-comparison to use instead of the global one
+    pub headers: Option<HeaderComparison>,
+
+    /// optional latency-budget to use instead of the global one
+    pub latency_budget: Option<LatencyBudget>,
+
+    /// optional timeouts to use instead of the global ones
+    #[serde(default)]
+    pub timeouts: UpstreamTimeouts,
+
+    /// max. number of in-flight mirror-tasks for this route, in addition to the global limit
+    pub max_mirror_tasks: Option<usize>,
+
+    /// optional sample-rate to use instead of the global one
+    pub sample_rate: Option<f64>,
+
+    /// optional value to derive the sampling-decision from, instead of the global one
+    pub sample_by: Option<SampleBy>,
 }
 
 impl Setting {
     pub(crate) fn emerge() -> Result<Setting, ConfigError> {
-        let config_file = std::env::var("MIFFY_CONFIG").unwrap_or("config.toml".to_string());
+        let config_file = config_file();
 
         let settings = config::Config::builder()
             .add_source(File::from_str(DEFAULT_CONFIG, FileFormat::Toml))
@@ -105,6 +574,39 @@ impl Setting {
     }
 }
 
+/// parse a config from the given TOML, on top of the default config
+#[cfg(test)]
+pub fn from_toml(toml: &str) -> Config {
+    config::Config::builder()
+        .add_source(File::from_str(DEFAULT_CONFIG, FileFormat::Toml))
+        .add_source(File::from_str(toml, FileFormat::Toml))
+        .build()
+        .and_then(config::Config::try_deserialize::<Config>)
+        .expect("config should be valid")
+}
+
+/// path of the config-file, `config.toml` unless overridden via `MIFFY_CONFIG`
+pub fn config_file() -> String {
+    std::env::var("MIFFY_CONFIG").unwrap_or("config.toml".to_string())
+}
+
+/// path of the config-file actually read, e.g. to watch it for changes
+pub fn config_path() -> PathBuf {
+    resolve(&config_file())
+}
+
+/// resolve the name of the config-file as `config::File::with_name` does: the file itself if it exists, else with the
+/// extension `.toml` appended (e.g. `/etc/miffy/config` to `/etc/miffy/config.toml`)
+fn resolve(name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.is_file() {
+        return path;
+    }
+    let mut with_extension = path.into_os_string();
+    with_extension.push(".toml");
+    PathBuf::from(with_extension)
+}
+
 /// collect env-vars into kafka-properties
 /// e.g. turns `KAFKA_BOOTSTRAP_SERVERS` into `bootstrap.servers`
 fn kafka_from_env(env_vars: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
@@ -121,8 +623,50 @@ fn kafka_from_env(env_vars: impl Iterator<Item = (String, String)>) -> Vec<(Stri
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod test {
-    use super::kafka_from_env;
+    use super::{Sink, from_toml, kafka_from_env, resolve};
+    use crate::util::temp_dir::TempDir;
+
+    #[test]
+    fn test_resolve_config_file() {
+        let dir = TempDir::new("config-path");
+        std::fs::write(dir.join("config.toml"), "").unwrap();
+        std::fs::write(dir.join("exact"), "").unwrap();
+
+        let name = |file: &str| dir.join(file).to_string_lossy().to_string();
+        assert_eq!(resolve(&name("config")), dir.join("config.toml"));
+        assert_eq!(resolve(&name("config.toml")), dir.join("config.toml"));
+        assert_eq!(resolve(&name("exact")), dir.join("exact"));
+    }
+
+    #[test]
+    fn test_instance() {
+        let toml = r#"
+            reference = "http://reference"
+            candidate = "http://candidate"
+            routes = []
+            "#;
+
+        assert_eq!(
+            from_toml(&format!("instance = \"miffy-1\"\n{toml}")).instance(),
+            "miffy-1"
+        );
+        // the hostname is always known on linux, even if the env-var isn't exported
+        #[cfg(target_os = "linux")]
+        assert_ne!(from_toml(toml).instance(), "unknown");
+    }
+
+    #[test]
+    fn test_sample_config() {
+        let config = from_toml(include_str!("../config.sample.toml"));
+
+        assert!(matches!(
+            config.sinks.as_slice(),
+            [Sink::Kafka, Sink::File { .. }]
+        ));
+        assert_eq!(config.routes.len(), 8);
+    }
 
     #[test]
     fn test_kafka_from_env() {
@@ -0,0 +1,372 @@
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("path must start with '$' (JSONPath) or '/' (JSON pointer): {0}")]
+    Root(String),
+
+    #[error("invalid path segment at position {1}: {0}")]
+    Segment(String, usize),
+}
+
+/// a single selector of a path-segment
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    /// object-key, or (if numeric) array-index
+    Key(String),
+    /// array-index, or (when used on an object) object-key
+    Index(usize),
+    /// all children
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// select direct children: `.name`, `['name']`, `[0]`, `.*`
+    Child(Selector),
+    /// select descendants at any depth: `..name`, `..*`
+    Descendant(Selector),
+}
+
+/// a (subset of) JSONPath (e.g. `$.meta.generatedAt`, `$..id`, `$.items[*].id`) or a JSON pointer (`/meta/generatedAt`).
+///
+/// Filter-expressions, slices and unions are not supported.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct JsonPath {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+impl Selector {
+    fn matches_key(&self, key: &str) -> bool {
+        match self {
+            Selector::Key(k) => k == key,
+            Selector::Index(i) => key.parse::<usize>().is_ok_and(|k| k == *i),
+            Selector::Wildcard => true,
+        }
+    }
+
+    fn matches_index(&self, index: usize) -> bool {
+        match self {
+            Selector::Key(k) => k.parse::<usize>().is_ok_and(|k| k == index),
+            Selector::Index(i) => *i == index,
+            Selector::Wildcard => true,
+        }
+    }
+
+    /// remove all matching children of the given value
+    fn remove(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => map.retain(|k, _| !self.matches_key(k)),
+            Value::Array(items) => {
+                let mut index = 0;
+                items.retain(|_| {
+                    let keep = !self.matches_index(index);
+                    index += 1;
+                    keep
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// all matching children of the given value
+    fn select_mut<'a>(
+        &'a self,
+        value: &'a mut Value,
+    ) -> Box<dyn Iterator<Item = &'a mut Value> + 'a> {
+        match value {
+            Value::Object(map) => Box::new(
+                map.iter_mut()
+                    .filter(|(k, _)| self.matches_key(k))
+                    .map(|(_, v)| v),
+            ),
+            Value::Array(items) => Box::new(
+                items
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(i, _)| self.matches_index(*i))
+                    .map(|(_, v)| v),
+            ),
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+}
+
+fn children_mut(value: &mut Value) -> Box<dyn Iterator<Item = &mut Value> + '_> {
+    match value {
+        Value::Object(map) => Box::new(map.values_mut()),
+        Value::Array(items) => Box::new(items.iter_mut()),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+fn remove_in(value: &mut Value, segments: &[Segment]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return;
+    };
+
+    match segment {
+        Segment::Child(selector) if rest.is_empty() => selector.remove(value),
+        Segment::Child(selector) => {
+            for child in selector.select_mut(value) {
+                remove_in(child, rest);
+            }
+        }
+        Segment::Descendant(selector) => {
+            // first apply the selector to the direct children, then descend (into what's left)
+            if rest.is_empty() {
+                selector.remove(value);
+            } else {
+                for child in selector.select_mut(value) {
+                    remove_in(child, rest);
+                }
+            }
+            for child in children_mut(value) {
+                remove_in(child, segments);
+            }
+        }
+    }
+}
+
+fn for_each_in<F: FnMut(&mut Value)>(value: &mut Value, segments: &[Segment], f: &mut F) {
+    let Some((segment, rest)) = segments.split_first() else {
+        f(value);
+        return;
+    };
+
+    match segment {
+        Segment::Child(selector) => {
+            for child in selector.select_mut(value) {
+                for_each_in(child, rest, f);
+            }
+        }
+        Segment::Descendant(selector) => {
+            for child in selector.select_mut(value) {
+                for_each_in(child, rest, f);
+            }
+            for child in children_mut(value) {
+                for_each_in(child, segments, f);
+            }
+        }
+    }
+}
+
+impl JsonPath {
+    /// call the given function for all values matching this path, e.g. to replace them
+    pub fn for_each_mut<F: FnMut(&mut Value)>(&self, value: &mut Value, mut f: F) {
+        for_each_in(value, &self.segments, &mut f);
+    }
+
+    /// remove all values matching this path from the given value.
+    ///
+    /// If the path points to the root itself, the value is replaced by `null`.
+    pub fn remove(&self, value: &mut Value) {
+        if self.segments.is_empty() {
+            *value = Value::Null;
+        } else {
+            remove_in(value, &self.segments);
+        }
+    }
+
+    fn parse_pointer(raw: &str) -> Vec<Segment> {
+        raw.split('/')
+            .skip(1)
+            .map(|s| s.replace("~1", "/").replace("~0", "~"))
+            .map(|s| Segment::Child(Selector::Key(s)))
+            .collect()
+    }
+
+    fn parse_json_path(raw: &str) -> Result<Vec<Segment>, Error> {
+        let mut segments = vec![];
+        // skip the leading '$'
+        let mut rest = &raw[1..];
+
+        while !rest.is_empty() {
+            let position = raw.len() - rest.len();
+            let invalid = || Error::Segment(raw.to_string(), position);
+
+            let (descendant, after_dots) = if let Some(r) = rest.strip_prefix("..") {
+                (true, r)
+            } else if let Some(r) = rest.strip_prefix('.') {
+                (false, r)
+            } else if rest.starts_with('[') {
+                (false, rest)
+            } else {
+                return Err(invalid());
+            };
+
+            let (selector, remaining) = if let Some(bracket) = after_dots.strip_prefix('[') {
+                let end = bracket.find(']').ok_or_else(invalid)?;
+                let inner = bracket[..end].trim();
+                let selector = if inner == "*" {
+                    Selector::Wildcard
+                } else if let Some(quoted) = inner
+                    .strip_prefix('\'')
+                    .and_then(|i| i.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|i| i.strip_suffix('"')))
+                {
+                    Selector::Key(quoted.to_string())
+                } else {
+                    Selector::Index(inner.parse().map_err(|_| invalid())?)
+                };
+                (selector, &bracket[end + 1..])
+            } else {
+                let end = after_dots.find(['.', '[']).unwrap_or(after_dots.len());
+                let name = &after_dots[..end];
+                let selector = match name {
+                    "" => return Err(invalid()),
+                    "*" => Selector::Wildcard,
+                    name => Selector::Key(name.to_string()),
+                };
+                (selector, &after_dots[end..])
+            };
+
+            segments.push(if descendant {
+                Segment::Descendant(selector)
+            } else {
+                Segment::Child(selector)
+            });
+            rest = remaining;
+        }
+
+        Ok(segments)
+    }
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = Error;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        let segments = if raw.is_empty() || raw.starts_with('/') {
+            Self::parse_pointer(&raw)
+        } else if raw.starts_with('$') {
+            Self::parse_json_path(&raw)?
+        } else {
+            return Err(Error::Root(raw));
+        };
+
+        Ok(Self { raw, segments })
+    }
+}
+
+impl TryFrom<&str> for JsonPath {
+    type Error = Error;
+
+    fn try_from(raw: &str) -> Result<Self, Self::Error> {
+        raw.to_string().try_into()
+    }
+}
+
+impl Serialize for JsonPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl Display for JsonPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::{Error, JsonPath};
+    use serde_json::json;
+
+    fn remove(path: &str, mut value: serde_json::Value) -> serde_json::Value {
+        JsonPath::try_from(path).unwrap().remove(&mut value);
+        value
+    }
+
+    #[test]
+    fn test_remove_child() {
+        let actual = remove(
+            "$.meta.generatedAt",
+            json!({"meta": {"generatedAt": "now", "version": 1}, "generatedAt": "now"}),
+        );
+
+        assert_eq!(
+            actual,
+            json!({"meta": {"version": 1}, "generatedAt": "now"})
+        );
+    }
+
+    #[test]
+    fn test_remove_descendants() {
+        let actual = remove(
+            "$..id",
+            json!({"id": 1, "items": [{"id": 2, "name": "a"}, {"nested": {"id": 3}}]}),
+        );
+
+        assert_eq!(actual, json!({"items": [{"name": "a"}, {"nested": {}}]}));
+    }
+
+    #[test]
+    fn test_remove_wildcard_and_index() {
+        let value = json!({"items": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}]});
+
+        assert_eq!(
+            remove("$.items[*].id", value.clone()),
+            json!({"items": [{"name": "a"}, {"name": "b"}]})
+        );
+        assert_eq!(
+            remove("$['items'][1]", value),
+            json!({"items": [{"id": 1, "name": "a"}]})
+        );
+    }
+
+    #[test]
+    fn test_remove_pointer() {
+        let actual = remove(
+            "/meta/a~1b",
+            json!({"meta": {"a/b": 1, "c": 2}, "list": [1, 2]}),
+        );
+        assert_eq!(actual, json!({"meta": {"c": 2}, "list": [1, 2]}));
+
+        let actual = remove("/list/0", json!({"list": [1, 2]}));
+        assert_eq!(actual, json!({"list": [2]}));
+    }
+
+    #[test]
+    fn test_remove_root() {
+        assert_eq!(remove("$", json!({"a": 1})), json!(null));
+        assert_eq!(remove("", json!({"a": 1})), json!(null));
+    }
+
+    #[test]
+    fn test_for_each_mut() {
+        let mut value = json!({"id": 1, "items": [{"id": 2}, {"nested": {"id": 3}}], "name": "a"});
+        JsonPath::try_from("$..id")
+            .unwrap()
+            .for_each_mut(&mut value, |v| *v = json!("x"));
+        assert_eq!(
+            value,
+            json!({"id": "x", "items": [{"id": "x"}, {"nested": {"id": "x"}}], "name": "a"})
+        );
+
+        JsonPath::try_from("/items/0")
+            .unwrap()
+            .for_each_mut(&mut value, |v| *v = json!(null));
+        assert_eq!(value["items"], json!([null, {"nested": {"id": "x"}}]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            JsonPath::try_from("meta.id"),
+            Err(Error::Root("meta.id".to_string()))
+        );
+        assert_eq!(
+            JsonPath::try_from("$.meta..").unwrap_err(),
+            Error::Segment("$.meta..".to_string(), 6)
+        );
+        assert!(JsonPath::try_from("$[abc]").is_err());
+    }
+}
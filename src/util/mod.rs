@@ -1,5 +1,9 @@
 #[cfg(feature = "gcloud")]
 mod gcloud;
 pub mod header_ext;
+pub mod json_path;
 pub mod log;
 pub mod serialization;
+#[cfg(test)]
+pub mod temp_dir;
+pub mod watch;
@@ -1,4 +1,5 @@
 use serde::{Serialize, Serializer};
+use std::time::{Duration, SystemTime};
 
 #[derive(Serialize)]
 struct Error<E>
@@ -21,6 +22,23 @@ where
     }
 }
 
+/// serialize a duration as (fractional) milliseconds
+pub fn millis<S: Serializer>(value: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(value.as_secs_f64() * 1000.0)
+}
+
+/// serialize an optional duration as (fractional) milliseconds
+pub fn optional_millis<S: Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    value.map(|d| d.as_secs_f64() * 1000.0).serialize(s)
+}
+
+/// serialize a point in time as RFC 3339, e.g. `2018-02-14T00:28:07.123Z`
+pub fn rfc3339<S: Serializer>(value: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(&humantime_serde::re::humantime::format_rfc3339_millis(
+        *value,
+    ))
+}
+
 #[cfg(test)]
 mod test {
     use serde::Serialize;
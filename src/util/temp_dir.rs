@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+/// a temporary directory for files of tests, e.g. certificates, removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// create the directory, unique per `name` and process
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("miffy-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temporary directory must be writable");
+        Self(dir)
+    }
+
+    /// path of a file in this directory
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// poll the modification-times of the given files, and call `on_change` whenever any of them changed.
+///
+/// Polling (instead of e.g. inotify) also notices files replaced via symlinks, as kubernetes does for mounted secrets.
+pub fn spawn(
+    paths: Vec<PathBuf>,
+    interval: Duration,
+    on_change: impl Fn() + Send + 'static,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last = modified(&paths);
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let current = modified(&paths);
+            if current != last {
+                on_change();
+                last = current;
+            }
+        }
+    })
+}